@@ -9,10 +9,10 @@ struct Backend {
 }
 
 #[tower_lsp::async_trait]
+#[tower_lsp::implemented_methods]
 impl LanguageServer for Backend {
     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
         Ok(InitializeResult {
-            server_info: None,
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
                     TextDocumentSyncKind::INCREMENTAL,
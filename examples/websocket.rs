@@ -3,9 +3,8 @@ use serde_json::Value;
 use tokio::net::TcpListener;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
-use tower_lsp::{Client, LanguageServer, LspService, Server};
+use tower_lsp::{websocket, Client, LanguageServer, LspService, Server};
 use tracing::info;
-use ws_stream_tungstenite::*;
 
 #[derive(Debug)]
 struct Backend {
@@ -16,7 +15,6 @@ struct Backend {
 impl LanguageServer for Backend {
     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
         Ok(InitializeResult {
-            server_info: None,
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
                     TextDocumentSyncKind::INCREMENTAL,
@@ -119,17 +117,12 @@ impl LanguageServer for Backend {
 
 #[tokio::main]
 async fn main() {
-    #[cfg(feature = "runtime-agnostic")]
-    use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
-
     tracing_subscriber::fmt().init();
 
     let listener = TcpListener::bind("127.0.0.1:9257").await.unwrap();
     info!("Listening on {}", listener.local_addr().unwrap());
     let (stream, _) = listener.accept().await.unwrap();
-    let (read, write) = tokio::io::split(WsStream::new(accept_async(stream).await.unwrap()));
-    #[cfg(feature = "runtime-agnostic")]
-    let (read, write) = (read.compat(), write.compat_write());
+    let (read, write) = websocket::split(accept_async(stream).await.unwrap());
 
     let (service, socket) = LspService::new(|client| Backend { client });
     Server::new(read, write, socket).serve(service).await;
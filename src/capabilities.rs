@@ -0,0 +1,316 @@
+//! Consistency checks between server capabilities and implemented methods.
+//!
+//! A server advertises the features it supports in the [`ServerCapabilities`] returned from
+//! `initialize`, but implements them by overriding methods of [`LanguageServer`]. Nothing forces
+//! the two to agree, so it is easy to advertise e.g. `hover_provider` while forgetting to
+//! implement [`LanguageServer::hover`], in which case every hover request fails.
+//!
+//! [`check`] compares both sides, given the list of implemented methods produced by
+//! [`#[tower_lsp::implemented_methods]`](macro@crate::implemented_methods). This check is also
+//! performed automatically by [`LspService`](crate::LspService), which logs any mismatches.
+//!
+//! [`LanguageServer`]: crate::LanguageServer
+//! [`LanguageServer::hover`]: crate::LanguageServer::hover
+
+use std::fmt::{self, Display, Formatter};
+
+use lsp_types::ServerCapabilities;
+use serde_json::Value;
+
+/// Capabilities, as `.`-separated paths into the serialized [`ServerCapabilities`], mapped to the
+/// methods a server advertising them must implement.
+const CAPABILITY_METHODS: &[(&str, &[&str])] = &[
+    ("textDocumentSync.willSave", &["textDocument/willSave"]),
+    (
+        "textDocumentSync.willSaveWaitUntil",
+        &["textDocument/willSaveWaitUntil"],
+    ),
+    ("completionProvider", &["textDocument/completion"]),
+    (
+        "completionProvider.resolveProvider",
+        &["completionItem/resolve"],
+    ),
+    ("hoverProvider", &["textDocument/hover"]),
+    ("signatureHelpProvider", &["textDocument/signatureHelp"]),
+    ("declarationProvider", &["textDocument/declaration"]),
+    ("definitionProvider", &["textDocument/definition"]),
+    ("typeDefinitionProvider", &["textDocument/typeDefinition"]),
+    ("implementationProvider", &["textDocument/implementation"]),
+    ("referencesProvider", &["textDocument/references"]),
+    (
+        "documentHighlightProvider",
+        &["textDocument/documentHighlight"],
+    ),
+    ("documentSymbolProvider", &["textDocument/documentSymbol"]),
+    ("workspaceSymbolProvider", &["workspace/symbol"]),
+    (
+        "workspaceSymbolProvider.resolveProvider",
+        &["workspaceSymbol/resolve"],
+    ),
+    ("codeActionProvider", &["textDocument/codeAction"]),
+    (
+        "codeActionProvider.resolveProvider",
+        &["codeAction/resolve"],
+    ),
+    ("codeLensProvider", &["textDocument/codeLens"]),
+    ("codeLensProvider.resolveProvider", &["codeLens/resolve"]),
+    ("documentFormattingProvider", &["textDocument/formatting"]),
+    (
+        "documentRangeFormattingProvider",
+        &["textDocument/rangeFormatting"],
+    ),
+    (
+        "documentOnTypeFormattingProvider",
+        &["textDocument/onTypeFormatting"],
+    ),
+    ("renameProvider", &["textDocument/rename"]),
+    (
+        "renameProvider.prepareProvider",
+        &["textDocument/prepareRename"],
+    ),
+    ("documentLinkProvider", &["textDocument/documentLink"]),
+    (
+        "documentLinkProvider.resolveProvider",
+        &["documentLink/resolve"],
+    ),
+    (
+        "colorProvider",
+        &[
+            "textDocument/documentColor",
+            "textDocument/colorPresentation",
+        ],
+    ),
+    ("foldingRangeProvider", &["textDocument/foldingRange"]),
+    ("executeCommandProvider", &["workspace/executeCommand"]),
+    ("selectionRangeProvider", &["textDocument/selectionRange"]),
+    (
+        "linkedEditingRangeProvider",
+        &["textDocument/linkedEditingRange"],
+    ),
+    (
+        "callHierarchyProvider",
+        &[
+            "textDocument/prepareCallHierarchy",
+            "callHierarchy/incomingCalls",
+            "callHierarchy/outgoingCalls",
+        ],
+    ),
+    (
+        "semanticTokensProvider.full",
+        &["textDocument/semanticTokens/full"],
+    ),
+    (
+        "semanticTokensProvider.full.delta",
+        &["textDocument/semanticTokens/full/delta"],
+    ),
+    (
+        "semanticTokensProvider.range",
+        &["textDocument/semanticTokens/range"],
+    ),
+    ("monikerProvider", &["textDocument/moniker"]),
+    (
+        "typeHierarchyProvider",
+        &[
+            "textDocument/prepareTypeHierarchy",
+            "typeHierarchy/supertypes",
+            "typeHierarchy/subtypes",
+        ],
+    ),
+    ("inlineValueProvider", &["textDocument/inlineValue"]),
+    ("inlayHintProvider", &["textDocument/inlayHint"]),
+    ("inlayHintProvider.resolveProvider", &["inlayHint/resolve"]),
+    ("diagnosticProvider", &["textDocument/diagnostic"]),
+    (
+        "diagnosticProvider.workspaceDiagnostics",
+        &["workspace/diagnostic"],
+    ),
+    (
+        "workspace.workspaceFolders.changeNotifications",
+        &["workspace/didChangeWorkspaceFolders"],
+    ),
+    (
+        "workspace.fileOperations.willCreate",
+        &["workspace/willCreateFiles"],
+    ),
+    (
+        "workspace.fileOperations.didCreate",
+        &["workspace/didCreateFiles"],
+    ),
+    (
+        "workspace.fileOperations.willRename",
+        &["workspace/willRenameFiles"],
+    ),
+    (
+        "workspace.fileOperations.didRename",
+        &["workspace/didRenameFiles"],
+    ),
+    (
+        "workspace.fileOperations.willDelete",
+        &["workspace/willDeleteFiles"],
+    ),
+    (
+        "workspace.fileOperations.didDelete",
+        &["workspace/didDeleteFiles"],
+    ),
+];
+
+/// A disagreement between the server capabilities and the implemented methods.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mismatch {
+    /// The capability is advertised, but the method serving it is not implemented.
+    ///
+    /// Requests for the capability will fail with a "method not found" error.
+    Unimplemented {
+        /// Path of the capability in the serialized [`ServerCapabilities`], e.g. `hoverProvider`.
+        capability: &'static str,
+        /// Name of the unimplemented method, e.g. `textDocument/hover`.
+        method: &'static str,
+    },
+    /// The method is implemented, but the capability enabling it is not advertised.
+    ///
+    /// The client will never call the method, unless the capability is registered dynamically.
+    Unadvertised {
+        /// Path of the capability in the serialized [`ServerCapabilities`], e.g. `hoverProvider`.
+        capability: &'static str,
+        /// Name of the implemented method, e.g. `textDocument/hover`.
+        method: &'static str,
+    },
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Unimplemented { capability, method } => {
+                write!(
+                    f,
+                    "`{}` is advertised, but `{}` is not implemented",
+                    capability, method
+                )
+            }
+            Mismatch::Unadvertised { capability, method } => {
+                write!(
+                    f,
+                    "`{}` is implemented, but `{}` is not advertised",
+                    method, capability
+                )
+            }
+        }
+    }
+}
+
+/// Checks the given server capabilities against the names of the implemented methods.
+///
+/// Returns every capability which is advertised but whose methods are not implemented, and every
+/// implemented method whose capability is not advertised. Methods which the client may call
+/// regardless of capabilities, like `initialize` or `textDocument/didOpen`, are never reported.
+///
+/// Note that capabilities registered dynamically with
+/// [`Client::register_capability`](crate::Client::register_capability) are legitimately absent
+/// from `capabilities`, so [`Mismatch::Unadvertised`] is not necessarily a bug.
+///
+/// # Example
+///
+/// ```rust
+/// use tower_lsp::capabilities::{self, Mismatch};
+/// use tower_lsp::lsp_types::*;
+///
+/// let capabilities = ServerCapabilities {
+///     hover_provider: Some(HoverProviderCapability::Simple(true)),
+///     ..Default::default()
+/// };
+///
+/// let implemented = ["initialize", "shutdown", "textDocument/completion"];
+/// assert_eq!(
+///     capabilities::check(&capabilities, &implemented),
+///     [
+///         Mismatch::Unadvertised {
+///             capability: "completionProvider",
+///             method: "textDocument/completion",
+///         },
+///         Mismatch::Unimplemented {
+///             capability: "hoverProvider",
+///             method: "textDocument/hover",
+///         },
+///     ],
+/// );
+/// ```
+pub fn check(capabilities: &ServerCapabilities, implemented: &[&str]) -> Vec<Mismatch> {
+    let capabilities = serde_json::to_value(capabilities).unwrap_or_default();
+
+    let mut mismatches = Vec::new();
+    for &(capability, methods) in CAPABILITY_METHODS {
+        let advertised = is_advertised(&capabilities, capability);
+        for &method in methods {
+            match (advertised, implemented.contains(&method)) {
+                (true, false) => mismatches.push(Mismatch::Unimplemented { capability, method }),
+                (false, true) => mismatches.push(Mismatch::Unadvertised { capability, method }),
+                _ => {}
+            }
+        }
+    }
+
+    mismatches
+}
+
+/// Returns `true` if the capability at the given path is present and not `false`.
+fn is_advertised(capabilities: &Value, path: &str) -> bool {
+    let value = path
+        .split('.')
+        .try_fold(capabilities, |value, key| value.get(key));
+
+    !matches!(value, None | Some(Value::Null) | Some(Value::Bool(false)))
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::*;
+
+    use super::*;
+
+    #[test]
+    fn maps_capabilities_to_known_methods() {
+        for (_, methods) in CAPABILITY_METHODS {
+            for method in *methods {
+                let known = crate::generated::LSP_METHODS.contains(method);
+                assert!(known, "`{}` is not a `LanguageServer` method", method);
+            }
+        }
+    }
+
+    #[test]
+    fn checks_nested_capabilities() {
+        let capabilities = ServerCapabilities {
+            code_lens_provider: Some(CodeLensOptions {
+                resolve_provider: Some(false),
+            }),
+            semantic_tokens_provider: Some(
+                SemanticTokensOptions {
+                    full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                    ..Default::default()
+                }
+                .into(),
+            ),
+            ..Default::default()
+        };
+
+        let implemented = [
+            "textDocument/codeLens",
+            "codeLens/resolve",
+            "textDocument/semanticTokens/full",
+        ];
+
+        assert_eq!(
+            check(&capabilities, &implemented),
+            [
+                Mismatch::Unadvertised {
+                    capability: "codeLensProvider.resolveProvider",
+                    method: "codeLens/resolve",
+                },
+                Mismatch::Unimplemented {
+                    capability: "semanticTokensProvider.full.delta",
+                    method: "textDocument/semanticTokens/full/delta",
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! In-process connection between a fake client and a service.
+
+use futures::channel::mpsc;
+use futures::future::{Fuse, FutureExt, LocalBoxFuture};
+use futures::StreamExt;
+use tower::Service;
+
+use crate::jsonrpc::{Message, Request, Response};
+use crate::{ClientSocket, Server};
+
+/// Client end of a [`Server`] talking to a service through in-memory channels.
+///
+/// The server is driven only while waiting in [`recv`](Self::recv), so no task is spawned.
+pub(crate) struct Connection {
+    server: Fuse<LocalBoxFuture<'static, ()>>,
+    input: Option<mpsc::UnboundedSender<Message>>,
+    output: mpsc::UnboundedReceiver<Message>,
+}
+
+impl Connection {
+    pub(crate) fn new<T>(service: T, socket: ClientSocket) -> Self
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        let (input, stdin) = mpsc::unbounded();
+        let (stdout, output) = mpsc::unbounded();
+        let server = Server::from_messages(stdin, stdout, socket).serve(service);
+
+        Connection {
+            server: server.boxed_local().fuse(),
+            input: Some(input),
+            output,
+        }
+    }
+
+    /// Sends a message to the server, unless the input was closed.
+    pub(crate) fn send(&self, msg: Message) {
+        if let Some(input) = &self.input {
+            let _ = input.unbounded_send(msg);
+        }
+    }
+
+    /// Closes the input of the server, which stops once it handled the remaining messages.
+    pub(crate) fn close(&mut self) {
+        self.input = None;
+    }
+
+    /// Drives the server until it sends a message, returning `None` once it stopped.
+    pub(crate) async fn recv(&mut self) -> Option<Message> {
+        loop {
+            futures::select! {
+                () = &mut self.server => {}
+                msg = self.output.next() => return msg,
+                complete => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{InitializeParams, InitializeResult};
+
+    use super::*;
+    use crate::jsonrpc::{self, Id};
+    use crate::{LanguageServer, LspService};
+
+    #[derive(Debug)]
+    struct Mock;
+
+    #[async_trait::async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: InitializeParams) -> jsonrpc::Result<InitializeResult> {
+            Ok(InitializeResult::default())
+        }
+
+        async fn shutdown(&self) -> jsonrpc::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn stops_after_close() {
+        let (service, socket) = LspService::new(|_| Mock);
+        let mut connection = Connection::new(service, socket);
+
+        let req = Request::build("shutdown").id(1).finish();
+        connection.send(Message::Request(req));
+        connection.close();
+
+        let expected = Response::from_error(Id::Number(1), jsonrpc::not_initialized_error());
+        assert_eq!(connection.recv().await, Some(Message::Response(expected)));
+        assert_eq!(connection.recv().await, None);
+    }
+}
@@ -0,0 +1,512 @@
+//! Built-in store of open text documents, kept in sync with the client.
+//!
+//! This module is only available when the `documents` crate feature is enabled.
+//!
+//! # Example
+//!
+//! ```rust
+//! use tower_lsp::documents::DocumentStore;
+//! use tower_lsp::jsonrpc::Result;
+//! use tower_lsp::lsp_types::*;
+//! use tower_lsp::{LanguageServer, LspService};
+//!
+//! #[derive(Debug, Default)]
+//! struct Backend {
+//!     documents: DocumentStore,
+//! }
+//!
+//! #[tower_lsp::async_trait]
+//! impl LanguageServer for Backend {
+//!     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//!         Ok(InitializeResult {
+//!             capabilities: ServerCapabilities {
+//!                 text_document_sync: Some(TextDocumentSyncKind::INCREMENTAL.into()),
+//!                 ..Default::default()
+//!             },
+//!             ..Default::default()
+//!         })
+//!     }
+//!
+//!     async fn shutdown(&self) -> Result<()> {
+//!         Ok(())
+//!     }
+//!
+//!     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+//!         let uri = &params.text_document_position_params.text_document.uri;
+//!         let document = match self.documents.get(uri) {
+//!             Some(document) => document,
+//!             None => return Ok(None),
+//!         };
+//!
+//!         let position = params.text_document_position_params.position;
+//!         let line = document.text().line(position.line as usize).to_string();
+//!         Ok(Some(Hover {
+//!             contents: HoverContents::Scalar(MarkedString::String(line)),
+//!             range: None,
+//!         }))
+//!     }
+//! }
+//!
+//! let documents = DocumentStore::new();
+//! let (service, socket) = LspService::build(|_| Backend { documents: documents.clone() })
+//!     .layer(documents.layer())
+//!     .finish();
+//! ```
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use dashmap::DashMap;
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification,
+};
+use lsp_types::{
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams, Position,
+    TextDocumentContentChangeEvent, Uri,
+};
+use ropey::{Rope, RopeSlice};
+use serde::de::DeserializeOwned;
+use tower::{Layer, Service};
+use tracing::warn;
+
+use crate::jsonrpc::Request;
+use crate::position::PositionEncoding;
+
+/// An open text document.
+///
+/// The text is stored in a [`Rope`], so applying an edit takes logarithmic time in the size of
+/// the document, and cloning a document to take a snapshot of it is cheap.
+#[derive(Clone, Debug)]
+pub struct Document {
+    language_id: String,
+    version: i32,
+    text: Rope,
+    encoding: PositionEncoding,
+}
+
+impl Document {
+    /// Creates a new document with the given language ID, version and text.
+    ///
+    /// Positions are interpreted in [`PositionEncoding::Utf16`], unless changed with
+    /// [`with_position_encoding`](Self::with_position_encoding).
+    pub fn new(language_id: String, version: i32, text: &str) -> Self {
+        Document {
+            language_id,
+            version,
+            text: Rope::from_str(text),
+            encoding: PositionEncoding::Utf16,
+        }
+    }
+
+    /// Sets the encoding in which positions referring to this document are interpreted.
+    pub fn with_position_encoding(mut self, encoding: PositionEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Returns the language ID of the document, e.g. `rust`.
+    pub fn language_id(&self) -> &str {
+        &self.language_id
+    }
+
+    /// Returns the version of the document, which increases after each change.
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Returns the text of the document.
+    pub fn text(&self) -> &Rope {
+        &self.text
+    }
+
+    /// Returns the encoding in which positions referring to this document are interpreted.
+    pub fn position_encoding(&self) -> PositionEncoding {
+        self.encoding
+    }
+
+    /// Applies the given content change to the document.
+    ///
+    /// If the change carries a range, only that range is replaced. Otherwise, the change replaces
+    /// the entire text of the document. Positions past the end of a line or of the document are
+    /// clamped to the end of the line or document, respectively.
+    pub fn apply_change(&mut self, change: &TextDocumentContentChangeEvent) {
+        match change.range {
+            Some(range) => {
+                let start = self.position_to_char(range.start);
+                let end = self.position_to_char(range.end).max(start);
+                self.text.remove(start..end);
+                self.text.insert(start, &change.text);
+            }
+            None => self.text = Rope::from_str(&change.text),
+        }
+    }
+
+    /// Converts the given position into a char index into the text.
+    pub fn position_to_char(&self, position: Position) -> usize {
+        let line = position.line as usize;
+        if line >= self.text.len_lines() {
+            return self.text.len_chars();
+        }
+
+        let line_start = self.text.line_to_char(line);
+        let line_end = line_start + line_len(self.text.line(line));
+
+        let start_unit = self.char_to_unit(line_start);
+        let end_unit = self.char_to_unit(line_end);
+        let unit = (start_unit + position.character as usize).min(end_unit);
+        self.unit_to_char(unit)
+    }
+
+    /// Converts the given char index into the text into a position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `char_idx` is out of bounds, i.e. `char_idx > self.text().len_chars()`.
+    pub fn char_to_position(&self, char_idx: usize) -> Position {
+        let line = self.text.char_to_line(char_idx);
+        let line_start = self.text.line_to_char(line);
+        let character = self.char_to_unit(char_idx) - self.char_to_unit(line_start);
+        Position::new(line as u32, character as u32)
+    }
+
+    /// Converts a char index into the number of preceding code units in the position encoding.
+    fn char_to_unit(&self, char_idx: usize) -> usize {
+        match self.encoding {
+            PositionEncoding::Utf8 => self.text.char_to_byte(char_idx),
+            PositionEncoding::Utf16 => self.text.char_to_utf16_cu(char_idx),
+            PositionEncoding::Utf32 => char_idx,
+        }
+    }
+
+    /// Converts a code unit index in the position encoding into a char index, rounding down.
+    fn unit_to_char(&self, unit_idx: usize) -> usize {
+        match self.encoding {
+            PositionEncoding::Utf8 => self.text.byte_to_char(unit_idx),
+            PositionEncoding::Utf16 => self.text.utf16_cu_to_char(unit_idx),
+            PositionEncoding::Utf32 => unit_idx,
+        }
+    }
+}
+
+/// Returns the length of the given line in chars, excluding its line terminator.
+fn line_len(line: RopeSlice) -> usize {
+    let mut len = line.len_chars();
+    if len > 0 && line.char(len - 1) == '\n' {
+        len -= 1;
+    }
+    if len > 0 && line.char(len - 1) == '\r' {
+        len -= 1;
+    }
+    len
+}
+
+/// A thread-safe map of open text documents, keyed by URI.
+///
+/// This type provides a very cheap implementation of [`Clone`], where every clone refers to the
+/// same underlying documents.
+///
+/// The store is kept in sync with the client either by calling [`did_open`](Self::did_open),
+/// [`did_change`](Self::did_change) and [`did_close`](Self::did_close) from the corresponding
+/// [`LanguageServer`](crate::LanguageServer) methods, or by adding the middleware returned by
+/// [`layer`](Self::layer) to the [`LspService`](crate::LspService), which does so automatically
+/// before the `LanguageServer` methods are called.
+#[derive(Clone, Default)]
+pub struct DocumentStore {
+    documents: Arc<DashMap<Uri, Document>>,
+    encoding: Arc<Mutex<PositionEncoding>>,
+}
+
+impl DocumentStore {
+    /// Creates a new, empty document store.
+    pub fn new() -> Self {
+        DocumentStore::default()
+    }
+
+    /// Returns a snapshot of the document with the given URI, if it is open.
+    ///
+    /// The snapshot is unaffected by any later changes to the document.
+    pub fn get(&self, uri: &Uri) -> Option<Document> {
+        self.documents.get(uri).map(|entry| entry.value().clone())
+    }
+
+    /// Returns the encoding in which incoming positions are interpreted.
+    pub fn position_encoding(&self) -> PositionEncoding {
+        *self.encoding.lock().unwrap()
+    }
+
+    /// Sets the encoding in which incoming positions are interpreted, for both open documents
+    /// and those opened later.
+    ///
+    /// This defaults to [`PositionEncoding::Utf16`]. Servers negotiating a different encoding
+    /// should set it from their [`initialize`](crate::LanguageServer::initialize) handler.
+    pub fn set_position_encoding(&self, encoding: PositionEncoding) {
+        *self.encoding.lock().unwrap() = encoding;
+        for mut document in self.documents.iter_mut() {
+            document.encoding = encoding;
+        }
+    }
+
+    /// Returns `true` if the document with the given URI is open.
+    pub fn contains(&self, uri: &Uri) -> bool {
+        self.documents.contains_key(uri)
+    }
+
+    /// Returns the URIs of all open documents.
+    pub fn uris(&self) -> Vec<Uri> {
+        self.documents
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Stores the document opened in the client.
+    ///
+    /// This corresponds to the [`textDocument/didOpen`] notification.
+    ///
+    /// [`textDocument/didOpen`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didOpen
+    pub fn did_open(&self, params: &DidOpenTextDocumentParams) {
+        let doc = &params.text_document;
+        let document = Document::new(doc.language_id.clone(), doc.version, &doc.text)
+            .with_position_encoding(self.position_encoding());
+        self.documents.insert(doc.uri.clone(), document);
+    }
+
+    /// Applies the content changes made in the client to the stored document.
+    ///
+    /// The changes are applied in the order they appear in `params`, as required by the
+    /// specification.
+    ///
+    /// This corresponds to the [`textDocument/didChange`] notification.
+    ///
+    /// [`textDocument/didChange`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didChange
+    pub fn did_change(&self, params: &DidChangeTextDocumentParams) {
+        let uri = &params.text_document.uri;
+        match self.documents.get_mut(uri) {
+            Some(mut document) => {
+                for change in &params.content_changes {
+                    document.apply_change(change);
+                }
+
+                document.version = params.text_document.version;
+            }
+            None => warn!(
+                "received changes for unknown document {}, ignoring",
+                uri.as_str()
+            ),
+        }
+    }
+
+    /// Forgets the document closed in the client.
+    ///
+    /// This corresponds to the [`textDocument/didClose`] notification.
+    ///
+    /// [`textDocument/didClose`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didClose
+    pub fn did_close(&self, params: &DidCloseTextDocumentParams) {
+        self.documents.remove(&params.text_document.uri);
+    }
+
+    /// Returns a middleware layer which keeps this store in sync with the client.
+    ///
+    /// The layer updates the store whenever a `textDocument/didOpen`, `textDocument/didChange` or
+    /// `textDocument/didClose` notification passes through it, before forwarding the notification
+    /// to the inner service. All other messages are forwarded unchanged. It is intended to be
+    /// added with [`LspServiceBuilder::layer`](crate::LspServiceBuilder::layer).
+    ///
+    /// Note that changes are only guaranteed to be applied in order if notifications are processed
+    /// sequentially, which is the default. See
+    /// [`LspServiceBuilder::ordered_notifications`](crate::LspServiceBuilder::ordered_notifications).
+    pub fn layer(&self) -> DocumentLayer {
+        DocumentLayer {
+            store: self.clone(),
+        }
+    }
+
+    fn sync(&self, req: &Request) {
+        match req.method() {
+            DidOpenTextDocument::METHOD => self.sync_with(req, Self::did_open),
+            DidChangeTextDocument::METHOD => self.sync_with(req, Self::did_change),
+            DidCloseTextDocument::METHOD => self.sync_with(req, Self::did_close),
+            _ => {}
+        }
+    }
+
+    fn sync_with<P: DeserializeOwned>(&self, req: &Request, f: fn(&Self, &P)) {
+        let params = req.params().cloned().unwrap_or_default();
+        match serde_json::from_value(params) {
+            Ok(params) => f(self, &params),
+            Err(err) => warn!("invalid params for {}, ignoring: {}", req.method(), err),
+        }
+    }
+}
+
+impl Debug for DocumentStore {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let uris: Vec<_> = self
+            .documents
+            .iter()
+            .map(|e| e.key().as_str().to_owned())
+            .collect();
+        f.debug_struct("DocumentStore")
+            .field("documents", &uris)
+            .field("encoding", &self.position_encoding())
+            .finish()
+    }
+}
+
+/// Middleware which keeps a [`DocumentStore`] in sync with the client.
+///
+/// This layer is created with [`DocumentStore::layer`].
+#[derive(Clone, Debug)]
+pub struct DocumentLayer {
+    store: DocumentStore,
+}
+
+impl<S> Layer<S> for DocumentLayer {
+    type Service = DocumentService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DocumentService {
+            inner,
+            store: self.store.clone(),
+        }
+    }
+}
+
+/// Service created from [`DocumentLayer`].
+#[derive(Clone, Debug)]
+pub struct DocumentService<S> {
+    inner: S,
+    store: DocumentStore,
+}
+
+impl<S: Service<Request>> Service<Request> for DocumentService<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        self.store.sync(&req);
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{Range, TextDocumentItem, VersionedTextDocumentIdentifier};
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn change(
+        range: Option<((u32, u32), (u32, u32))>,
+        text: &str,
+    ) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: range.map(|((l1, c1), (l2, c2))| {
+                Range::new(Position::new(l1, c1), Position::new(l2, c2))
+            }),
+            range_length: None,
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn applies_incremental_changes() {
+        let mut document = Document::new("rust".into(), 1, "fn main() {\r\n    let 😀 = 1;\n}\n");
+
+        // Replace the `1` after the emoji, which takes up two UTF-16 code units.
+        document.apply_change(&change(Some(((1, 13), (1, 14))), "42"));
+        assert_eq!(
+            document.text().to_string(),
+            "fn main() {\r\n    let 😀 = 42;\n}\n"
+        );
+
+        // Positions past the end of a line are clamped to before the line terminator.
+        document.apply_change(&change(Some(((0, 11), (0, 99))), " // entry"));
+        assert_eq!(
+            document.text().to_string(),
+            "fn main() { // entry\r\n    let 😀 = 42;\n}\n"
+        );
+
+        // Insert at the very end of the document.
+        document.apply_change(&change(Some(((3, 0), (3, 0))), "// end\n"));
+        assert_eq!(
+            document.text().to_string(),
+            "fn main() { // entry\r\n    let 😀 = 42;\n}\n// end\n"
+        );
+
+        document.apply_change(&change(None, "fn main() {}"));
+        assert_eq!(document.text().to_string(), "fn main() {}");
+    }
+
+    #[test]
+    fn converts_positions() {
+        let document = Document::new("rust".into(), 1, "a😀b\nc");
+
+        assert_eq!(document.position_to_char(Position::new(0, 3)), 2);
+        assert_eq!(document.char_to_position(2), Position::new(0, 3));
+        assert_eq!(document.position_to_char(Position::new(1, 1)), 5);
+        assert_eq!(document.char_to_position(5), Position::new(1, 1));
+        assert_eq!(document.position_to_char(Position::new(5, 0)), 5);
+
+        let document = document.with_position_encoding(PositionEncoding::Utf8);
+        assert_eq!(document.position_to_char(Position::new(0, 5)), 2);
+        assert_eq!(document.char_to_position(2), Position::new(0, 5));
+
+        let document = document.with_position_encoding(PositionEncoding::Utf32);
+        assert_eq!(document.position_to_char(Position::new(0, 2)), 2);
+        assert_eq!(document.char_to_position(2), Position::new(0, 2));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn syncs_documents_through_layer() {
+        let store = DocumentStore::new();
+        let calls = Arc::new(Mutex::new(0));
+        let inner_calls = calls.clone();
+        let mut service = store.layer().layer(tower::service_fn(move |_: Request| {
+            *inner_calls.lock().unwrap() += 1;
+            async { Ok::<_, std::convert::Infallible>(None::<()>) }
+        }));
+
+        let uri: Uri = "file:///foo.rs".parse().unwrap();
+        let open = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri.clone(), "rust".into(), 1, "foo".into()),
+        };
+        let open = Request::from_notification::<DidOpenTextDocument>(open);
+        service.ready().await.unwrap().call(open).await.unwrap();
+
+        let snapshot = store.get(&uri).unwrap();
+
+        let change = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+            content_changes: vec![change(Some(((0, 3), (0, 3))), "bar")],
+        };
+        let change = Request::from_notification::<DidChangeTextDocument>(change);
+        service.ready().await.unwrap().call(change).await.unwrap();
+
+        let document = store.get(&uri).unwrap();
+        assert_eq!(document.version(), 2);
+        assert_eq!(document.text().to_string(), "foobar");
+        assert_eq!(snapshot.version(), 1);
+        assert_eq!(snapshot.text().to_string(), "foo");
+
+        let invalid = Request::build("textDocument/didClose")
+            .params(json!({}))
+            .finish();
+        service.ready().await.unwrap().call(invalid).await.unwrap();
+        assert!(store.contains(&uri));
+
+        let close = Request::build("textDocument/didClose")
+            .params(json!({"textDocument":{"uri":"file:///foo.rs"}}))
+            .finish();
+        service.ready().await.unwrap().call(close).await.unwrap();
+        assert!(store.get(&uri).is_none());
+        assert_eq!(*calls.lock().unwrap(), 4);
+    }
+}
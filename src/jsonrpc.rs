@@ -109,14 +109,71 @@ impl Serialize for Version {
 }
 
 /// An incoming or outgoing JSON-RPC message.
-#[derive(Deserialize, Serialize)]
-#[cfg_attr(test, derive(Debug, PartialEq))]
+///
+/// This is the unit exchanged with the client by message-level transports, see
+/// [`Server::from_messages`](crate::Server::from_messages).
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(untagged)]
-pub(crate) enum Message {
+pub enum Message {
     /// A response message.
     Response(Response),
     /// A request or notification message.
     Request(Request),
+    /// A batch of requests, notifications and/or responses.
+    ///
+    /// See the [JSON-RPC 2.0 specification](https://www.jsonrpc.org/specification#batch) for
+    /// details.
+    Batch(Vec<Message>),
+}
+
+/// An incoming JSON-RPC message, as decoded from the wire.
+///
+/// Unlike [`Message`], the elements of a batch are decoded separately, so that an invalid element
+/// does not invalidate the whole batch.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Incoming {
+    /// A response message.
+    Response(Response),
+    /// A request or notification message.
+    Request(Request),
+    /// A batch of messages, each of which may have failed to decode.
+    Batch(Vec<Result<Message>>),
+}
+
+impl From<Message> for Incoming {
+    fn from(msg: Message) -> Self {
+        match msg {
+            Message::Response(res) => Incoming::Response(res),
+            Message::Request(req) => Incoming::Request(req),
+            Message::Batch(batch) => Incoming::Batch(batch.into_iter().map(Ok).collect()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Incoming {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Response(Response),
+            Request(Request),
+            Batch(Vec<serde_json::Value>),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Response(res) => Incoming::Response(res),
+            Raw::Request(req) => Incoming::Request(req),
+            Raw::Batch(batch) => Incoming::Batch(
+                batch
+                    .into_iter()
+                    .map(|v| serde_json::from_value(v).map_err(|_| Error::invalid_request()))
+                    .collect(),
+            ),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -191,6 +248,66 @@ mod tests {
         assert!(matches!(incoming, Message::Request(_)));
     }
 
+    #[test]
+    fn parses_batch_message() {
+        let batch = json!([
+            {"jsonrpc":"2.0","method":"textDocument/hover","params":{},"id":0},
+            {"jsonrpc":"2.0","method":"initialized","params":{}},
+            {"jsonrpc":"2.0","id":1,"result":null},
+        ]);
+        let incoming = serde_json::from_value(batch).unwrap();
+        match incoming {
+            Message::Batch(messages) => {
+                assert!(matches!(messages[0], Message::Request(_)));
+                assert!(matches!(messages[1], Message::Request(_)));
+                assert!(matches!(messages[2], Message::Response(_)));
+            }
+            other => panic!("expected batch message, got {other:?}"),
+        }
+
+        let empty_batch = json!([]);
+        let incoming = serde_json::from_value(empty_batch).unwrap();
+        assert!(matches!(incoming, Message::Batch(batch) if batch.is_empty()));
+    }
+
+    #[test]
+    fn decodes_batch_elements_separately() {
+        let batch = json!([
+            1,
+            {"jsonrpc":"2.0","method":"initialized","params":{}},
+            {"jsonrpc":"2.0","id":1,"result":null},
+        ]);
+        let incoming: Incoming = serde_json::from_value(batch).unwrap();
+        match incoming {
+            Incoming::Batch(messages) => {
+                assert_eq!(messages[0], Err(Error::invalid_request()));
+                assert!(matches!(messages[1], Ok(Message::Request(_))));
+                assert!(matches!(messages[2], Ok(Message::Response(_))));
+            }
+            other => panic!("expected batch message, got {other:?}"),
+        }
+
+        let single = json!({"jsonrpc":"2.0","method":"initialized","params":{}});
+        let incoming: Incoming = serde_json::from_value(single).unwrap();
+        assert!(matches!(incoming, Incoming::Request(_)));
+    }
+
+    #[test]
+    fn serializes_batch_message() {
+        let batch = Message::Batch(vec![
+            Message::Response(Response::from_ok(0.into(), json!(null))),
+            Message::Response(Response::from_error(1.into(), Error::method_not_found())),
+        ]);
+        let serialized = serde_json::to_value(batch).unwrap();
+        assert_eq!(
+            serialized,
+            json!([
+                {"jsonrpc":"2.0","result":null,"id":0},
+                {"jsonrpc":"2.0","error":{"code":-32601,"message":"Method not found"},"id":1},
+            ])
+        );
+    }
+
     #[test]
     fn accepts_null_request_id() {
         let request_id: Id = serde_json::from_value(json!(null)).unwrap();
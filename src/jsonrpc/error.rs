@@ -171,6 +171,39 @@ impl Error {
     pub const fn content_modified() -> Self {
         Error::new(ErrorCode::ContentModified)
     }
+
+    /// Creates a new "request timed out" error (`-32099`).
+    ///
+    /// This is returned by [`Client::send_request`] when the client does not respond to a request
+    /// in time. The code lies in the range reserved for implementation-defined server errors, so it
+    /// is represented as [`ErrorCode::ServerError`].
+    ///
+    /// [`Client::send_request`]: crate::Client::send_request
+    pub const fn request_timed_out() -> Self {
+        Error {
+            code: ErrorCode::ServerError(-32099),
+            message: Cow::Borrowed("Request timed out"),
+            data: None,
+        }
+    }
+
+    /// Creates a new "unsupported by client" error (`-32098`) with the given message.
+    ///
+    /// This is returned by [`Client`] methods instead of sending a request which the client
+    /// declared in its capabilities not to support. Like [`Error::request_timed_out`], its code is
+    /// an [`ErrorCode::ServerError`].
+    ///
+    /// [`Client`]: crate::Client
+    pub fn unsupported_by_client<M>(message: M) -> Self
+    where
+        M: Into<Cow<'static, str>>,
+    {
+        Error {
+            code: ErrorCode::ServerError(-32098),
+            message: message.into(),
+            data: None,
+        }
+    }
 }
 
 impl Display for Error {
@@ -214,4 +247,25 @@ mod tests {
         let deserialized: ErrorCode = serde_json::from_str("-12345").unwrap();
         assert_eq!(deserialized, ErrorCode::ServerError(-12345));
     }
+
+    #[test]
+    fn request_timed_out_round_trips() {
+        let serialized = serde_json::to_string(&Error::request_timed_out()).unwrap();
+        assert_eq!(
+            serialized,
+            r#"{"code":-32099,"message":"Request timed out"}"#
+        );
+
+        let deserialized: Error = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, Error::request_timed_out());
+    }
+
+    #[test]
+    fn unsupported_by_client_round_trips() {
+        let error = Error::unsupported_by_client("client does not support `foo`");
+        let serialized = serde_json::to_string(&error).unwrap();
+        let deserialized: Error = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, error);
+        assert_eq!(deserialized.code, ErrorCode::ServerError(-32098));
+    }
 }
@@ -82,8 +82,50 @@ pub extern crate lsp_types;
 /// A re-export of [`async-trait`](https://docs.rs/async-trait) for convenience.
 pub use async_trait::async_trait;
 
-pub use self::service::{Client, ClientSocket, ExitedError, LspService, LspServiceBuilder};
-pub use self::transport::{Loopback, Server};
+/// Records which [`LanguageServer`] methods are implemented by a server.
+///
+/// This attribute annotates an `impl LanguageServer` block and generates an implementation of
+/// [`LanguageServer::implemented_methods`] listing every method defined in that block. The
+/// [`LspService`] then checks the capabilities returned from `initialize` against this list and
+/// logs any capability which is advertised but not implemented, or vice versa.
+///
+/// # Example
+///
+/// ```rust
+/// use tower_lsp::jsonrpc::Result;
+/// use tower_lsp::lsp_types::*;
+/// use tower_lsp::LanguageServer;
+///
+/// #[derive(Debug)]
+/// struct Backend;
+///
+/// #[tower_lsp::async_trait]
+/// #[tower_lsp::implemented_methods]
+/// impl LanguageServer for Backend {
+///     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+///         Ok(InitializeResult::default())
+///     }
+///
+///     async fn shutdown(&self) -> Result<()> {
+///         Ok(())
+///     }
+///
+///     async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+///         Ok(None)
+///     }
+/// }
+///
+/// let methods = Backend.implemented_methods().unwrap();
+/// assert_eq!(methods, ["initialize", "shutdown", "textDocument/hover"]);
+/// ```
+pub use tower_lsp_macros::implemented_methods;
+
+pub use self::service::{
+    CancelHandle, Client, ClientSocket, ExitedError, LspService, LspServiceBuilder, MethodKind,
+    MethodService, PartialResults, ProgressReporter, RegistrationGuard, Settings,
+    UnknownMethodError,
+};
+pub use self::transport::{Loopback, Messages, Server};
 
 use auto_impl::auto_impl;
 use lsp_types::request::{
@@ -97,9 +139,21 @@ use tracing::{error, warn};
 
 use self::jsonrpc::{Error, Result};
 
+pub mod capabilities;
+#[cfg(feature = "documents")]
+pub mod documents;
 pub mod jsonrpc;
+#[cfg(feature = "listener")]
+pub mod listener;
+pub mod position;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod trace;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 mod codec;
+mod connection;
 mod service;
 mod transport;
 
@@ -230,6 +284,73 @@ pub trait LanguageServer: Send + Sync + 'static {
         warn!("Got a textDocument/didClose notification, but it is not implemented");
     }
 
+    // Notebook Document Synchronization
+
+    /// The [`notebookDocument/didOpen`] notification is sent from the client to the server when a
+    /// new notebook document has been opened by the client.
+    ///
+    /// [`notebookDocument/didOpen`]: https://microsoft.github.io/language-server-protocol/specification#notebookDocument_didOpen
+    ///
+    /// It is only sent for notebook documents selected by the `notebook_document_sync` server
+    /// capability. The text documents of the notebook's cells are included in the params, and
+    /// are not announced separately via [`did_open`](Self::did_open).
+    ///
+    /// # Compatibility
+    ///
+    /// This notification was introduced in specification version 3.17.0.
+    #[rpc(name = "notebookDocument/didOpen")]
+    async fn notebook_did_open(&self, params: DidOpenNotebookDocumentParams) {
+        let _ = params;
+        warn!("Got a notebookDocument/didOpen notification, but it is not implemented");
+    }
+
+    /// The [`notebookDocument/didChange`] notification is sent from the client to the server when
+    /// a notebook document changes.
+    ///
+    /// [`notebookDocument/didChange`]: https://microsoft.github.io/language-server-protocol/specification#notebookDocument_didChange
+    ///
+    /// This notification covers changes to the notebook's metadata, as well as cells being added,
+    /// removed or modified, including edits to the contents of their text documents.
+    ///
+    /// # Compatibility
+    ///
+    /// This notification was introduced in specification version 3.17.0.
+    #[rpc(name = "notebookDocument/didChange")]
+    async fn notebook_did_change(&self, params: DidChangeNotebookDocumentParams) {
+        let _ = params;
+        warn!("Got a notebookDocument/didChange notification, but it is not implemented");
+    }
+
+    /// The [`notebookDocument/didSave`] notification is sent from the client to the server when a
+    /// notebook document is saved.
+    ///
+    /// [`notebookDocument/didSave`]: https://microsoft.github.io/language-server-protocol/specification#notebookDocument_didSave
+    ///
+    /// # Compatibility
+    ///
+    /// This notification was introduced in specification version 3.17.0.
+    #[rpc(name = "notebookDocument/didSave")]
+    async fn notebook_did_save(&self, params: DidSaveNotebookDocumentParams) {
+        let _ = params;
+        warn!("Got a notebookDocument/didSave notification, but it is not implemented");
+    }
+
+    /// The [`notebookDocument/didClose`] notification is sent from the client to the server when a
+    /// notebook document is closed.
+    ///
+    /// [`notebookDocument/didClose`]: https://microsoft.github.io/language-server-protocol/specification#notebookDocument_didClose
+    ///
+    /// The text documents of the notebook's cells are closed along with it.
+    ///
+    /// # Compatibility
+    ///
+    /// This notification was introduced in specification version 3.17.0.
+    #[rpc(name = "notebookDocument/didClose")]
+    async fn notebook_did_close(&self, params: DidCloseNotebookDocumentParams) {
+        let _ = params;
+        warn!("Got a notebookDocument/didClose notification, but it is not implemented");
+    }
+
     // Language Features
 
     /// The [`textDocument/declaration`] request asks the server for the declaration location of a
@@ -1201,6 +1322,9 @@ pub trait LanguageServer: Send + Sync + 'static {
     ///
     /// This notification is also sent if the server has registered itself to receive this
     /// notification.
+    ///
+    /// The changes are also applied to the workspace folders tracked by the service, which can be
+    /// queried with [`Client::current_workspace_folders`].
     #[rpc(name = "workspace/didChangeWorkspaceFolders")]
     async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
         let _ = params;
@@ -1326,8 +1450,28 @@ pub trait LanguageServer: Send + Sync + 'static {
         Err(Error::method_not_found())
     }
 
-    // TODO: Add `work_done_progress_cancel()` here (since 3.15.0) when supported by `tower-lsp`.
-    // https://github.com/ebkalderon/tower-lsp/issues/176
+    // Introspection
+
+    /// Returns the names of the LSP methods implemented by this server, if known.
+    ///
+    /// Rather than overriding this method by hand, annotate the `impl LanguageServer` block with
+    /// [`#[tower_lsp::implemented_methods]`](macro@implemented_methods), which generates it from
+    /// the methods defined in that block.
+    ///
+    /// If this returns `Some`, the server capabilities returned from [`initialize`] are checked
+    /// against it with [`capabilities::check`], and any mismatches are logged.
+    ///
+    /// [`initialize`]: Self::initialize
+    fn implemented_methods(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+}
+
+#[doc(hidden)]
+pub mod __private {
+    //! Items used by the code generated by `#[implemented_methods]`. Not public API.
+
+    pub use crate::generated::methods;
 }
 
 fn _assert_object_safe() {
@@ -0,0 +1,326 @@
+//! Listener serving many clients, each with its own language server instance.
+//!
+//! Language servers are usually started by the editor and talk to it over standard I/O. A
+//! [`Listener`] instead accepts any number of connections on a TCP or Unix domain socket, as a
+//! daemon shared between editors would, and creates a fresh [`LspService`](crate::LspService)
+//! for each of them. Every connection is served on its own `tokio` task, so a slow request of one
+//! client does not hold up the others, and the future returned from the listener completes once
+//! all of these tasks have finished.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # use tower_lsp::jsonrpc::Result;
+//! # use tower_lsp::lsp_types::*;
+//! # use tower_lsp::{Client, LanguageServer, LspService};
+//! use tokio::net::TcpListener;
+//! use tower_lsp::listener::Listener;
+//!
+//! struct Backend {
+//!     client: Client,
+//! }
+//!
+//! #[tower_lsp::async_trait]
+//! impl LanguageServer for Backend {
+//!     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//!         Ok(InitializeResult::default())
+//!     }
+//!
+//!     async fn shutdown(&self) -> Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let tcp = TcpListener::bind("127.0.0.1:9257").await.unwrap();
+//!     let listener = Listener::new(|| LspService::new(|client| Backend { client }));
+//!
+//!     // Calling `handle.shutdown()`, e.g. on Ctrl-C, stops the listener.
+//!     let handle = listener.shutdown_handle();
+//! #   drop(handle);
+//!
+//!     listener.serve_tcp(tcp).await;
+//! }
+//! ```
+
+use std::fmt::{self, Debug, Formatter};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use futures::channel::oneshot;
+use futures::future::{self, FutureExt, Shared};
+use futures::stream::{self, FuturesUnordered, Stream, StreamExt};
+use futures::Sink;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::task::JoinError;
+use tower::Service;
+use tracing::{error, info};
+
+use crate::jsonrpc::{Request, Response};
+use crate::transport::{Loopback, Server, DEFAULT_MAX_CONCURRENCY};
+
+/// Handle for stopping a running [`Listener`].
+///
+/// This type is cheap to clone. Dropping it does not stop the listener.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    tx: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+}
+
+impl ShutdownHandle {
+    /// Stops the listener.
+    ///
+    /// The listener stops accepting new connections and gracefully stops every connection
+    /// currently being served, as described in [`Server::serve_with_shutdown`]. Calling this more
+    /// than once has no effect.
+    pub fn shutdown(&self) {
+        if let Some(tx) = self.tx.lock().unwrap().take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+impl Debug for ShutdownHandle {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let triggered = self.tx.lock().unwrap().is_none();
+        f.debug_struct("ShutdownHandle")
+            .field("triggered", &triggered)
+            .finish()
+    }
+}
+
+/// Listener accepting client connections and serving each of them with a new service.
+///
+/// See the [module-level documentation](self) for details.
+pub struct Listener<F> {
+    factory: F,
+    max_concurrency: usize,
+    handle: ShutdownHandle,
+    signal: Shared<oneshot::Receiver<()>>,
+}
+
+impl<F, T, L> Listener<F>
+where
+    F: FnMut() -> (T, L),
+    T: Service<Request, Response = Option<Response>> + Send + 'static,
+    T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    T::Future: Send,
+    L: Loopback + Send + 'static,
+    L::RequestStream: Send,
+    L::ResponseSink: Send,
+    <L::ResponseSink as Sink<Response>>::Error: std::error::Error,
+{
+    /// Creates a new `Listener` which calls `factory` to create the service and client socket
+    /// for every accepted connection.
+    pub fn new(factory: F) -> Self {
+        let (tx, rx) = oneshot::channel();
+        Listener {
+            factory,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            handle: ShutdownHandle {
+                tx: Arc::new(Mutex::new(Some(tx))),
+            },
+            signal: rx.shared(),
+        }
+    }
+
+    /// Sets the concurrency limit of the server of every connection to `max`.
+    ///
+    /// See [`Server::concurrency_level`] for details.
+    pub fn concurrency_level(mut self, max: usize) -> Self {
+        self.max_concurrency = max;
+        self
+    }
+
+    /// Returns a handle for stopping this listener once it is running.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.handle.clone()
+    }
+
+    /// Serves every connection accepted by the given TCP listener.
+    ///
+    /// This returns once the listener is stopped with its [`ShutdownHandle`] and every connection
+    /// has been closed. Failures to accept a connection are logged and otherwise ignored.
+    pub async fn serve_tcp(self, listener: TcpListener) {
+        let incoming = stream::unfold(listener, |listener| async {
+            let conn = listener.accept().await;
+            Some((conn.map(|(stream, _)| stream.into_split()), listener))
+        });
+
+        self.serve_incoming(incoming).await
+    }
+
+    /// Serves every connection accepted by the given Unix domain socket listener.
+    ///
+    /// See [`Listener::serve_tcp`] for details.
+    #[cfg(unix)]
+    pub async fn serve_unix(self, listener: UnixListener) {
+        let incoming = stream::unfold(listener, |listener| async {
+            let conn = listener.accept().await;
+            Some((conn.map(|(stream, _)| stream.into_split()), listener))
+        });
+
+        self.serve_incoming(incoming).await
+    }
+
+    /// Serves every connection yielded by `incoming`, as pairs of read and write halves.
+    ///
+    /// Each connection is served on a task spawned with [`tokio::spawn`], so this must be called
+    /// from within a `tokio` runtime.
+    ///
+    /// This returns once the listener is stopped with its [`ShutdownHandle`] or `incoming` ends,
+    /// and every connection has been closed. Errors yielded by `incoming` are logged and otherwise
+    /// ignored.
+    pub async fn serve_incoming<S, I, O>(mut self, incoming: S)
+    where
+        S: Stream<Item = io::Result<(I, O)>>,
+        I: AsyncRead + Unpin + Send + 'static,
+        O: AsyncWrite + Send + 'static,
+    {
+        // Only an explicit shutdown stops the listener, not dropping the last handle.
+        let shared = self.signal.clone();
+        let signal = move || {
+            let shared = shared.clone();
+            async move {
+                if shared.await.is_err() {
+                    future::pending().await
+                }
+            }
+        };
+
+        let mut incoming = Box::pin(incoming.take_until(signal()).fuse());
+        let mut connections = FuturesUnordered::new();
+
+        loop {
+            futures::select! {
+                conn = incoming.next() => match conn {
+                    Some(Ok((read, write))) => {
+                        let (service, socket) = (self.factory)();
+                        let server = Server::new(read, write, socket)
+                            .concurrency_level(self.max_concurrency);
+                        let serve = server.serve_with_shutdown(service, signal());
+                        connections.push(tokio::spawn(serve));
+                    }
+                    Some(Err(err)) => error!("failed to accept connection: {}", err),
+                    None => break,
+                },
+                result = connections.select_next_some() => connection_finished(result),
+            }
+        }
+
+        info!("stopped accepting connections");
+        while let Some(result) = connections.next().await {
+            connection_finished(result);
+        }
+    }
+}
+
+fn connection_finished(result: Result<(), JoinError>) {
+    if let Err(err) = result {
+        error!("failed to serve connection: {}", err);
+    }
+}
+
+impl<F> Debug for Listener<F> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Listener")
+            .field("max_concurrency", &self.max_concurrency)
+            .field("handle", &self.handle)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::jsonrpc::Result;
+    use crate::lsp_types::*;
+    use crate::{LanguageServer, LspService};
+
+    #[derive(Debug)]
+    struct Mock;
+
+    #[async_trait::async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+            Ok(InitializeResult::default())
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    const REQUEST: &str =
+        r#"{"jsonrpc":"2.0","method":"initialize","params":{"capabilities":{}},"id":1}"#;
+    const RESPONSE: &str = r#"{"jsonrpc":"2.0","result":{"capabilities":{}},"id":1}"#;
+
+    async fn initialize<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S) {
+        let message = format!("Content-Length: {}\r\n\r\n{}", REQUEST.len(), REQUEST);
+        stream.write_all(message.as_bytes()).await.unwrap();
+
+        let expected = format!("Content-Length: {}\r\n\r\n{}", RESPONSE.len(), RESPONSE);
+        let mut response = vec![0; expected.len()];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(String::from_utf8(response).unwrap(), expected);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn serves_multiple_clients() {
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+
+        let listener = Listener::new(|| LspService::new(|_| Mock));
+        let handle = listener.shutdown_handle();
+
+        let clients = async move {
+            let mut first = TcpStream::connect(addr).await.unwrap();
+            let mut second = TcpStream::connect(addr).await.unwrap();
+
+            initialize(&mut second).await;
+            initialize(&mut first).await;
+
+            handle.shutdown();
+
+            // Both connections are closed by the server.
+            let mut rest = Vec::new();
+            assert_eq!(first.read_to_end(&mut rest).await.unwrap(), 0);
+            assert_eq!(second.read_to_end(&mut rest).await.unwrap(), 0);
+        };
+
+        futures::join!(listener.serve_tcp(tcp), clients);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "current_thread")]
+    async fn serves_unix_clients() {
+        use tokio::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("tower-lsp-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let unix = UnixListener::bind(&path).unwrap();
+
+        let listener = Listener::new(|| LspService::new(|_| Mock));
+        let handle = listener.shutdown_handle();
+
+        let client = async {
+            let mut stream = UnixStream::connect(&path).await.unwrap();
+            initialize(&mut stream).await;
+
+            handle.shutdown();
+
+            let mut rest = Vec::new();
+            assert_eq!(stream.read_to_end(&mut rest).await.unwrap(), 0);
+        };
+
+        futures::join!(listener.serve_unix(unix), client);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
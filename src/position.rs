@@ -0,0 +1,294 @@
+//! Conversions between LSP positions and byte offsets.
+//!
+//! The column of an LSP [`Position`] counts code units in the position encoding negotiated
+//! during `initialize`, which is UTF-16 unless both the client and server agree otherwise. The
+//! functions in this module convert positions and ranges expressed in any of the supported
+//! encodings into byte offsets into a Rust `&str`, and back.
+//!
+//! As required by the specification, lines may be terminated by `\n`, `\r\n` or `\r`. Positions
+//! past the end of a line or of the text are clamped to the end of the line or text,
+//! respectively, and positions which fall in the middle of a character are rounded down to the
+//! start of that character.
+//!
+//! # Example
+//!
+//! ```rust
+//! use tower_lsp::lsp_types::Position;
+//! use tower_lsp::position::{self, PositionEncoding};
+//!
+//! let text = "let 😀 = 1;\nlet b = 2;";
+//!
+//! // The emoji takes up two UTF-16 code units, but four bytes.
+//! let offset = position::position_to_offset(text, Position::new(0, 7), PositionEncoding::Utf16);
+//! assert_eq!(&text[offset..], "= 1;\nlet b = 2;");
+//!
+//! let position = position::offset_to_position(text, offset, PositionEncoding::Utf8);
+//! assert_eq!(position, Position::new(0, 9));
+//! ```
+
+use std::ops;
+
+use lsp_types::{ClientCapabilities, Position, PositionEncodingKind, Range};
+use memchr::{memchr2, memchr2_iter, memrchr2};
+
+/// An encoding in which the columns of [`Position`]s are expressed.
+///
+/// See the [module-level documentation](self) for details.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum PositionEncoding {
+    /// Columns count UTF-8 code units, i.e. bytes.
+    Utf8,
+    /// Columns count UTF-16 code units.
+    ///
+    /// This is the default encoding, which every client and server must support.
+    #[default]
+    Utf16,
+    /// Columns count UTF-32 code units, i.e. Unicode scalar values.
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Returns the encoding corresponding to the given kind, or `None` if it is unknown.
+    pub fn from_kind(kind: &PositionEncodingKind) -> Option<Self> {
+        match kind.as_str() {
+            "utf-8" => Some(PositionEncoding::Utf8),
+            "utf-16" => Some(PositionEncoding::Utf16),
+            "utf-32" => Some(PositionEncoding::Utf32),
+            _ => None,
+        }
+    }
+
+    /// Returns the kind corresponding to this encoding.
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+            PositionEncoding::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    /// Picks the first of the `preferred` encodings which is supported by the client.
+    ///
+    /// Falls back to [`PositionEncoding::Utf16`] if the client supports none of them. The result
+    /// is meant to be returned in the `position_encoding` field of the server capabilities.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::position::PositionEncoding;
+    /// #
+    /// # let params = InitializeParams::default();
+    /// let encoding = PositionEncoding::negotiate(
+    ///     &params.capabilities,
+    ///     &[PositionEncoding::Utf8, PositionEncoding::Utf32],
+    /// );
+    ///
+    /// let capabilities = ServerCapabilities {
+    ///     position_encoding: Some(encoding.kind()),
+    ///     ..Default::default()
+    /// };
+    /// ```
+    pub fn negotiate(capabilities: &ClientCapabilities, preferred: &[PositionEncoding]) -> Self {
+        let supported = capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_deref())
+            .unwrap_or_default();
+
+        preferred
+            .iter()
+            .copied()
+            .find(|encoding| supported.contains(&encoding.kind()))
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of code units needed to encode `c`.
+    fn len(self, c: char) -> usize {
+        match self {
+            PositionEncoding::Utf8 => c.len_utf8(),
+            PositionEncoding::Utf16 => c.len_utf16(),
+            PositionEncoding::Utf32 => 1,
+        }
+    }
+
+    /// Returns the number of code units needed to encode `s`.
+    fn len_str(self, s: &str) -> usize {
+        match self {
+            PositionEncoding::Utf8 => s.len(),
+            PositionEncoding::Utf16 => s.encode_utf16().count(),
+            PositionEncoding::Utf32 => s.chars().count(),
+        }
+    }
+}
+
+impl From<PositionEncoding> for PositionEncodingKind {
+    fn from(encoding: PositionEncoding) -> Self {
+        encoding.kind()
+    }
+}
+
+/// Converts the given position into a byte offset into `text`.
+pub fn position_to_offset(text: &str, position: Position, encoding: PositionEncoding) -> usize {
+    let start = match line_start(text, position.line) {
+        Some(start) => start,
+        None => return text.len(),
+    };
+
+    let end = memchr2(b'\n', b'\r', &text.as_bytes()[start..]).map_or(text.len(), |i| start + i);
+    let target = position.character as usize;
+
+    let mut units = 0;
+    for (i, c) in text[start..end].char_indices() {
+        units += encoding.len(c);
+        if units > target {
+            return start + i;
+        }
+    }
+
+    end
+}
+
+/// Converts the given byte offset into `text` into a position.
+///
+/// Offsets past the end of `text` are clamped to the end of `text`.
+pub fn offset_to_position(text: &str, offset: usize, encoding: PositionEncoding) -> Position {
+    let bytes = text.as_bytes();
+
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    // An offset between `\r` and `\n` belongs to the end of the line they terminate.
+    if offset > 0 && bytes[offset - 1] == b'\r' && bytes.get(offset) == Some(&b'\n') {
+        offset -= 1;
+    }
+
+    let start = memrchr2(b'\n', b'\r', &bytes[..offset]).map_or(0, |i| i + 1);
+    let line = line_breaks(&bytes[..start]);
+    let character = encoding.len_str(&text[start..offset]);
+
+    Position::new(line as u32, character as u32)
+}
+
+/// Converts the given range into a range of byte offsets into `text`.
+pub fn range_to_offsets(text: &str, range: Range, encoding: PositionEncoding) -> ops::Range<usize> {
+    let start = position_to_offset(text, range.start, encoding);
+    let end = position_to_offset(text, range.end, encoding).max(start);
+    start..end
+}
+
+/// Converts the given range of byte offsets into `text` into a range.
+pub fn offsets_to_range(
+    text: &str,
+    offsets: ops::Range<usize>,
+    encoding: PositionEncoding,
+) -> Range {
+    let start = offset_to_position(text, offsets.start, encoding);
+    let end = offset_to_position(text, offsets.end, encoding);
+    Range::new(start, end)
+}
+
+/// Returns the byte offset at which the given line starts, or `None` if there is no such line.
+fn line_start(text: &str, line: u32) -> Option<usize> {
+    if line == 0 {
+        return Some(0);
+    }
+
+    let bytes = text.as_bytes();
+    let mut remaining = line;
+    for i in memchr2_iter(b'\n', b'\r', bytes) {
+        // The line ends with the `\n` of a `\r\n` pair, not with the `\r`.
+        if bytes[i] == b'\r' && bytes.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+
+        remaining -= 1;
+        if remaining == 0 {
+            return Some(i + 1);
+        }
+    }
+
+    None
+}
+
+/// Returns the number of line breaks in `bytes`, counting each `\r\n` pair once.
+fn line_breaks(bytes: &[u8]) -> usize {
+    memchr2_iter(b'\n', b'\r', bytes)
+        .filter(|&i| !(bytes[i] == b'\r' && bytes.get(i + 1) == Some(&b'\n')))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::GeneralClientCapabilities;
+
+    use super::*;
+
+    const TEXT: &str = "aé😀b\r\nc\rd\n";
+
+    #[test]
+    fn converts_positions() {
+        use PositionEncoding::*;
+
+        // Byte offsets of `b` and of the line terminator after it, per encoding.
+        for (encoding, b, eol) in [(Utf8, 7, 8), (Utf16, 4, 5), (Utf32, 3, 4)] {
+            let to_offset = |line, character| {
+                position_to_offset(TEXT, Position::new(line, character), encoding)
+            };
+
+            assert_eq!(to_offset(0, b), 7, "{:?}", encoding);
+            assert_eq!(to_offset(0, eol), 8, "{:?}", encoding);
+            assert_eq!(to_offset(0, 99), 8, "{:?}", encoding);
+            assert_eq!(to_offset(1, 0), 10, "{:?}", encoding);
+            assert_eq!(to_offset(2, 1), 13, "{:?}", encoding);
+            assert_eq!(to_offset(3, 0), 14, "{:?}", encoding);
+            assert_eq!(to_offset(4, 0), 14, "{:?}", encoding);
+
+            let to_position = |offset| offset_to_position(TEXT, offset, encoding);
+            assert_eq!(to_position(7), Position::new(0, b), "{:?}", encoding);
+            assert_eq!(to_position(9), Position::new(0, eol), "{:?}", encoding);
+            assert_eq!(to_position(10), Position::new(1, 0), "{:?}", encoding);
+            assert_eq!(to_position(12), Position::new(2, 0), "{:?}", encoding);
+            assert_eq!(to_position(99), Position::new(3, 0), "{:?}", encoding);
+        }
+
+        // Positions and offsets in the middle of the emoji are rounded down.
+        assert_eq!(position_to_offset(TEXT, Position::new(0, 3), Utf16), 3);
+        assert_eq!(position_to_offset(TEXT, Position::new(0, 5), Utf8), 3);
+        assert_eq!(offset_to_position(TEXT, 5, Utf16), Position::new(0, 2));
+    }
+
+    #[test]
+    fn converts_ranges() {
+        let range = Range::new(Position::new(0, 2), Position::new(1, 1));
+        let offsets = range_to_offsets(TEXT, range, PositionEncoding::Utf16);
+        assert_eq!(&TEXT[offsets.clone()], "😀b\r\nc");
+        assert_eq!(
+            offsets_to_range(TEXT, offsets, PositionEncoding::Utf16),
+            range
+        );
+
+        let backwards = Range::new(Position::new(1, 0), Position::new(0, 0));
+        assert_eq!(
+            range_to_offsets(TEXT, backwards, PositionEncoding::Utf8),
+            10..10
+        );
+    }
+
+    #[test]
+    fn negotiates_encoding() {
+        let preferred = [PositionEncoding::Utf8, PositionEncoding::Utf32];
+        let mut capabilities = ClientCapabilities::default();
+        let negotiated = PositionEncoding::negotiate(&capabilities, &preferred);
+        assert_eq!(negotiated, PositionEncoding::Utf16);
+
+        capabilities.general = Some(GeneralClientCapabilities {
+            position_encodings: Some(vec![PositionEncodingKind::UTF32, "utf-8".into()]),
+            ..Default::default()
+        });
+        let negotiated = PositionEncoding::negotiate(&capabilities, &preferred);
+        assert_eq!(negotiated, PositionEncoding::Utf8);
+    }
+}
@@ -1,18 +1,28 @@
 //! Service abstraction for language servers.
 
-pub use self::client::{Client, ClientSocket, RequestStream, ResponseSink};
+pub use self::client::{
+    CancelHandle, Client, ClientSocket, PartialResults, ProgressReporter, RegistrationGuard,
+    RequestStream, ResponseSink, Settings,
+};
 
+pub use self::invalidation::MethodKind;
+pub use self::layers::MethodService;
 pub(crate) use self::pending::Pending;
 pub(crate) use self::state::{ServerState, State};
 
+use std::collections::HashMap;
 use std::fmt::{self, Debug, Display, Formatter};
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use futures::future::{self, BoxFuture, FutureExt};
 use serde_json::Value;
-use tower::Service;
+use tower::layer::util::Stack;
+use tower::{BoxError, Layer, Service};
 
+use self::invalidation::Invalidation;
+use self::ordering::Sequencer;
 use crate::jsonrpc::{
     Error, ErrorCode, FromParams, IntoResponse, Method, Request, Response, Router,
 };
@@ -21,6 +31,8 @@ use crate::LanguageServer;
 pub(crate) mod layers;
 
 mod client;
+mod invalidation;
+mod ordering;
 mod pending;
 mod state;
 
@@ -36,6 +48,25 @@ impl Display for ExitedError {
     }
 }
 
+/// Error that occurs when attempting to add middleware to a method which does not exist.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnknownMethodError(&'static str);
+
+impl UnknownMethodError {
+    /// Returns the name of the unknown method.
+    pub fn method(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl std::error::Error for UnknownMethodError {}
+
+impl Display for UnknownMethodError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "no method named `{}` exists", self.0)
+    }
+}
+
 /// Service abstraction for the Language Server Protocol.
 ///
 /// This service takes an incoming JSON-RPC message as input and produces an outgoing message as
@@ -52,10 +83,29 @@ impl Display for ExitedError {
 /// The service shuts down and stops serving requests after the [`exit`] notification is received.
 ///
 /// [`exit`]: https://microsoft.github.io/language-server-protocol/specification#exit
+///
+/// # Notification ordering
+///
+/// Notifications handled by the language server, such as [`textDocument/didChange`], are processed
+/// sequentially in the order they were passed to [`call`](Service::call), even if the returned
+/// futures are polled concurrently. A notification handler only starts running once the handler
+/// of the previous notification has completed. Requests are not affected by this and still run
+/// concurrently with each other and with notifications. This guarantee can be disabled with
+/// [`LspServiceBuilder::ordered_notifications`].
+///
+/// [`textDocument/didChange`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didChange
+///
+/// # Stale requests
+///
+/// Optionally, pending read-only requests for a text document can be cancelled automatically
+/// once the document is modified. See [`LspServiceBuilder::cancel_stale_requests`] for details.
 #[derive(Debug)]
 pub struct LspService<S> {
     inner: Router<S, ExitedError>,
     state: Arc<ServerState>,
+    pending: Arc<Pending>,
+    notifications: Option<Sequencer>,
+    invalidation: Option<Invalidation>,
 }
 
 impl<S: LanguageServer> LspService<S> {
@@ -82,15 +132,16 @@ impl<S: LanguageServer> LspService<S> {
         let pending = Arc::new(Pending::new());
 
         LspServiceBuilder {
-            inner: crate::generated::register_lsp_methods(
-                inner,
-                state.clone(),
-                pending.clone(),
-                client,
-            ),
+            inner,
             state,
             pending,
+            client,
             socket,
+            middleware: layers::Middleware::new(),
+            custom_methods: Vec::new(),
+            ordered_notifications: true,
+            cancel_stale_requests: false,
+            method_kinds: HashMap::new(),
         }
     }
 
@@ -118,7 +169,20 @@ impl<S: LanguageServer> Service<Request> for LspService<S> {
             return future::err(ExitedError(())).boxed();
         }
 
-        let fut = self.inner.call(req);
+        let id = req.id().cloned();
+        let classified = match &self.invalidation {
+            Some(invalidation) => invalidation.classify(&req, &self.pending),
+            None => None,
+        };
+
+        let fut = match &mut self.notifications {
+            Some(seq) if seq.is_ordered(&req) => seq.schedule(self.inner.call(req)),
+            _ => self.inner.call(req),
+        };
+
+        if let (Some(id), Some((MethodKind::ReadOnly, uri))) = (id, classified) {
+            self.pending.set_document(&id, uri);
+        }
 
         Box::pin(async move {
             let response = fut.await?;
@@ -142,9 +206,17 @@ pub struct LspServiceBuilder<S> {
     inner: Router<S, ExitedError>,
     state: Arc<ServerState>,
     pending: Arc<Pending>,
+    client: Client,
     socket: ClientSocket,
+    middleware: layers::Middleware,
+    custom_methods: Vec<(&'static str, RegisterFn<S>)>,
+    ordered_notifications: bool,
+    cancel_stale_requests: bool,
+    method_kinds: HashMap<&'static str, Option<MethodKind>>,
 }
 
+type RegisterFn<S> = Box<dyn FnOnce(&mut Router<S, ExitedError>, &layers::Middleware)>;
+
 impl<S: LanguageServer> LspServiceBuilder<S> {
     /// Defines a custom JSON-RPC request or notification with the given method `name` and handler.
     ///
@@ -219,8 +291,186 @@ impl<S: LanguageServer> LspServiceBuilder<S> {
         R: IntoResponse,
         F: for<'a> Method<&'a S, P, R> + Clone + Send + Sync + 'static,
     {
-        let layer = layers::Normal::new(self.state.clone(), self.pending.clone());
-        self.inner.method(name, callback, layer);
+        let normal = layers::Normal::new(self.state.clone(), self.pending.clone());
+        let register: RegisterFn<S> = Box::new(move |router, middleware| {
+            let layer = Stack::new(middleware.for_method(name), normal);
+            router.method(name, callback, layer);
+        });
+
+        self.custom_methods.push((name, register));
+        self
+    }
+
+    /// Wraps every method handler of the language server with the given [`Layer`].
+    ///
+    /// This applies to all [`LanguageServer`] methods, as well as any custom methods registered
+    /// with [`custom_method`](Self::custom_method). The layer is inserted directly around each
+    /// handler, beneath the built-in middleware which implements the server lifecycle and
+    /// `$/cancelRequest` semantics. This makes it suitable for per-method timeouts, rate limits,
+    /// metrics and the like, which also observe only requests the server is ready to handle.
+    ///
+    /// Every method gets its own instance of the service produced by the layer. Layers are
+    /// applied in the order they are added, i.e. the first layer added is the outermost one.
+    ///
+    /// If the resulting service fails with an error, a JSON-RPC error response with code `-32603`
+    /// (Internal Error) is sent to the client.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower::ServiceExt;
+    /// use tower::layer::layer_fn;
+    /// use tower_lsp::jsonrpc::{Request, Result};
+    /// use tower_lsp::lsp_types::*;
+    /// use tower_lsp::{LanguageServer, LspService};
+    ///
+    /// struct Mock;
+    ///
+    /// // Implementation of `LanguageServer` omitted...
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    ///
+    /// let (service, socket) = LspService::build(|_| Mock)
+    ///     .layer(layer_fn(|service: tower_lsp::MethodService| {
+    ///         service.map_request(|req: Request| {
+    ///             eprintln!("handling {}", req.method());
+    ///             req
+    ///         })
+    ///     }))
+    ///     .finish();
+    /// ```
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<MethodService> + Send + Sync + 'static,
+        L::Service: Service<Request, Response = Option<Response>> + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<BoxError>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.middleware = self.middleware.push(layer);
+        self
+    }
+
+    /// Wraps the handler of the method `name` with the given [`Layer`].
+    ///
+    /// The `name` may refer to either a [`LanguageServer`] method, e.g. `textDocument/completion`,
+    /// or a custom method registered beforehand with [`custom_method`](Self::custom_method).
+    /// Layers added with this method are placed beneath those added with [`layer`](Self::layer),
+    /// and are otherwise applied in the same manner.
+    ///
+    /// Returns [`UnknownMethodError`] if no method with the given `name` exists.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower::ServiceExt;
+    /// use tower::layer::layer_fn;
+    /// use tower_lsp::jsonrpc::{Request, Result};
+    /// use tower_lsp::lsp_types::*;
+    /// use tower_lsp::{LanguageServer, LspService};
+    ///
+    /// struct Mock;
+    ///
+    /// // Implementation of `LanguageServer` omitted...
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    ///
+    /// let log_completion = layer_fn(|service: tower_lsp::MethodService| {
+    ///     service.map_request(|req: Request| {
+    ///         eprintln!("completion requested");
+    ///         req
+    ///     })
+    /// });
+    ///
+    /// let (service, socket) = LspService::build(|_| Mock)
+    ///     .method_layer("textDocument/completion", log_completion)
+    ///     .expect("method exists")
+    ///     .finish();
+    /// ```
+    pub fn method_layer<L>(
+        mut self,
+        name: &'static str,
+        layer: L,
+    ) -> Result<Self, UnknownMethodError>
+    where
+        L: Layer<MethodService> + Send + Sync + 'static,
+        L::Service: Service<Request, Response = Option<Response>> + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<BoxError>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        let is_lsp_method = crate::generated::LSP_METHODS.contains(&name);
+        if !is_lsp_method && !self.custom_methods.iter().any(|(n, _)| *n == name) {
+            return Err(UnknownMethodError(name));
+        }
+
+        self.middleware = self.middleware.push_method(name, layer);
+        Ok(self)
+    }
+
+    /// Sets whether notifications are processed sequentially in the order they arrive.
+    ///
+    /// This is enabled by default, which ensures that e.g. consecutive `textDocument/didChange`
+    /// notifications for the same document are always applied in order. Disabling it allows
+    /// notification handlers to run concurrently, subject to the same concurrency limits as
+    /// requests. See the [`LspService`] documentation for details.
+    pub fn ordered_notifications(mut self, enabled: bool) -> Self {
+        self.ordered_notifications = enabled;
+        self
+    }
+
+    /// Sets whether pending read-only requests are cancelled once their text document is modified.
+    ///
+    /// When enabled, every incoming message is classified as either [`MethodKind::Mutating`] or
+    /// [`MethodKind::ReadOnly`], or neither, and associated with the text document or notebook
+    /// document referenced by the `textDocument.uri` or `notebookDocument.uri` field of its
+    /// params. Whenever a mutating message arrives, all pending read-only requests for the same
+    /// document are cancelled and respond with [`Error::content_modified`], as their results
+    /// would be stale anyway.
+    ///
+    /// By default, the `didOpen`, `didChange` and `didClose` notifications are mutating, while all
+    /// other `textDocument/*` requests are read-only. This can be changed for individual methods
+    /// with [`method_kind`](Self::method_kind).
+    ///
+    /// This is disabled by default.
+    pub fn cancel_stale_requests(mut self, enabled: bool) -> Self {
+        self.cancel_stale_requests = enabled;
+        self
+    }
+
+    /// Overrides the [`MethodKind`] of the method `name`.
+    ///
+    /// Passing `None` excludes the method from classification entirely. This setting only takes
+    /// effect if [`cancel_stale_requests`](Self::cancel_stale_requests) is enabled.
+    pub fn method_kind(mut self, name: &'static str, kind: Option<MethodKind>) -> Self {
+        self.method_kinds.insert(name, kind);
+        self
+    }
+
+    /// Sets the default timeout for server-to-client requests sent through the [`Client`].
+    ///
+    /// If the client does not respond to a request in time, the request is cancelled and
+    /// [`Client::send_request`] returns [`Error::request_timed_out`]. By default, requests wait for
+    /// a response indefinitely.
+    ///
+    /// The timeout can be overridden for individual requests with
+    /// [`Client::send_request_with_timeout`].
+    pub fn request_timeout(self, timeout: Duration) -> Self {
+        self.client.set_request_timeout(timeout);
         self
     }
 
@@ -230,11 +480,46 @@ impl<S: LanguageServer> LspServiceBuilder<S> {
         let LspServiceBuilder {
             inner,
             state,
+            pending,
+            client,
             socket,
-            ..
+            middleware,
+            custom_methods,
+            ordered_notifications,
+            cancel_stale_requests,
+            method_kinds,
         } = self;
 
-        (LspService { inner, state }, socket)
+        let notifications = ordered_notifications.then(|| {
+            let custom = custom_methods.iter().map(|(name, _)| *name);
+            Sequencer::new(crate::generated::LSP_METHODS.iter().copied().chain(custom))
+        });
+
+        let invalidation = cancel_stale_requests.then(|| Invalidation::new(method_kinds));
+        let implemented = inner.inner().implemented_methods();
+
+        let mut inner = crate::generated::register_lsp_methods(
+            inner,
+            state.clone(),
+            pending.clone(),
+            client,
+            &middleware,
+            implemented,
+        );
+
+        for (_, register) in custom_methods {
+            register(&mut inner, &middleware);
+        }
+
+        let service = LspService {
+            inner,
+            state,
+            pending,
+            notifications,
+            invalidation,
+        };
+
+        (service, socket)
     }
 }
 
@@ -255,6 +540,7 @@ mod tests {
 
     use super::*;
     use crate::jsonrpc::Result;
+    use crate::position::PositionEncoding;
 
     #[derive(Debug)]
     struct Mock;
@@ -273,6 +559,14 @@ mod tests {
         async fn code_action_resolve(&self, _: CodeAction) -> Result<CodeAction> {
             future::pending().await
         }
+
+        // ...and neither should this one.
+        async fn document_highlight(
+            &self,
+            _: DocumentHighlightParams,
+        ) -> Result<Option<Vec<DocumentHighlight>>> {
+            future::pending().await
+        }
     }
 
     impl Mock {
@@ -362,6 +656,199 @@ mod tests {
         assert_eq!(cancel_response, Ok(None));
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn cancels_stale_requests() {
+        let (mut service, _) = LspService::build(|_| Mock)
+            .cancel_stale_requests(true)
+            .finish();
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+
+        let highlight_request = Request::build("textDocument/documentHighlight")
+            .params(
+                json!({"textDocument":{"uri":"file:///a.rs"},"position":{"line":0,"character":0}}),
+            )
+            .id(2)
+            .finish();
+
+        let did_change = Request::build("textDocument/didChange")
+            .params(json!({"textDocument":{"uri":"file:///a.rs","version":1},"contentChanges":[]}))
+            .finish();
+
+        let highlight_fut = service.ready().await.unwrap().call(highlight_request);
+        let change_fut = service.ready().await.unwrap().call(did_change);
+        let (highlight_response, change_response) = futures::join!(highlight_fut, change_fut);
+
+        let modified = Response::from_error(2.into(), Error::content_modified());
+        assert_eq!(highlight_response, Ok(Some(modified)));
+        assert_eq!(change_response, Ok(None));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn tracks_trace_value() {
+        let mut client = None;
+        let (mut service, _) = LspService::new(|c| {
+            client = Some(c);
+            Mock
+        });
+        let client = client.unwrap();
+        assert_eq!(client.trace_value(), TraceValue::Off);
+
+        let initialize = Request::build("initialize")
+            .params(json!({"capabilities":{},"trace":"messages"}))
+            .id(1)
+            .finish();
+        let response = service.ready().await.unwrap().call(initialize).await;
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+        assert_eq!(client.trace_value(), TraceValue::Messages);
+
+        let set_trace = Request::build("$/setTrace")
+            .params(json!({"value":"verbose"}))
+            .finish();
+        let response = service.ready().await.unwrap().call(set_trace).await;
+        assert_eq!(response, Ok(None));
+        assert_eq!(client.trace_value(), TraceValue::Verbose);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn records_position_encoding() {
+        #[derive(Debug)]
+        struct Utf8Server;
+
+        #[async_trait]
+        impl LanguageServer for Utf8Server {
+            async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+                let preferred = [PositionEncoding::Utf8];
+                let encoding = PositionEncoding::negotiate(&params.capabilities, &preferred);
+                Ok(InitializeResult {
+                    capabilities: ServerCapabilities {
+                        position_encoding: Some(encoding.kind()),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+            }
+
+            async fn shutdown(&self) -> Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut client = None;
+        let (mut service, _) = LspService::new(|c| {
+            client = Some(c);
+            Utf8Server
+        });
+        let client = client.unwrap();
+        assert_eq!(client.position_encoding(), PositionEncoding::Utf16);
+
+        let initialize = Request::build("initialize")
+            .params(json!({"capabilities":{"general":{"positionEncodings":["utf-8","utf-16"]}}}))
+            .id(1)
+            .finish();
+        let response = service.ready().await.unwrap().call(initialize).await;
+        let ok = Response::from_ok(
+            1.into(),
+            json!({"capabilities":{"positionEncoding":"utf-8"}}),
+        );
+        assert_eq!(response, Ok(Some(ok)));
+        assert_eq!(client.position_encoding(), PositionEncoding::Utf8);
+        assert!(client.capabilities().unwrap().general.is_some());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn tracks_workspace_folders() {
+        let mut client = None;
+        let (mut service, _) = LspService::new(|c| {
+            client = Some(c);
+            Mock
+        });
+        let client = client.unwrap();
+
+        let initialize = Request::build("initialize")
+            .params(json!({"capabilities":{},"rootUri":"file:///foo"}))
+            .id(1)
+            .finish();
+        let response = service.ready().await.unwrap().call(initialize).await;
+        assert!(response.unwrap().unwrap().is_ok());
+
+        let folders = client.current_workspace_folders();
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0].name, "foo");
+
+        let changed = Request::build("workspace/didChangeWorkspaceFolders")
+            .params(json!({"event":{
+                "added":[{"uri":"file:///bar","name":"bar"}],
+                "removed":[{"uri":"file:///foo","name":"foo"}],
+            }}))
+            .finish();
+        let response = service.ready().await.unwrap().call(changed).await;
+        assert_eq!(response, Ok(None));
+
+        let uri = "file:///bar/baz.rs".parse().unwrap();
+        let folder = client.workspace_folder_of(&uri).unwrap();
+        assert_eq!(folder.name, "bar");
+        assert_eq!(client.current_workspace_folders(), vec![folder]);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn updates_settings() {
+        use futures::{SinkExt, StreamExt};
+
+        let changes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut settings = None;
+        let (mut service, socket) = LspService::new(|client| {
+            let cache = Settings::<Value>::new(client, "mock");
+            let seen = changes.clone();
+            cache.on_change(move |value| {
+                seen.lock().unwrap().push((*value).clone());
+                future::ready(())
+            });
+            settings = Some(cache);
+            Mock
+        });
+        let settings = settings.unwrap();
+        let (mut requests, mut responses) = socket.split();
+
+        let initialize = Request::build("initialize")
+            .params(json!({"capabilities":{"workspace":{"configuration":true}}}))
+            .id(1)
+            .finish();
+        let response = service.ready().await.unwrap().call(initialize).await;
+        assert!(response.unwrap().unwrap().is_ok());
+
+        let notifications = [
+            Request::build("initialized").params(json!({})).finish(),
+            Request::build("workspace/didChangeConfiguration")
+                .params(json!({ "settings": null }))
+                .finish(),
+        ];
+
+        for (notification, value) in notifications.into_iter().zip([1, 2]) {
+            let server = service.ready().await.unwrap().call(notification);
+            let client = async {
+                let request = requests.next().await.unwrap();
+                assert_eq!(request.method(), "workspace/configuration");
+                let id = request.id().cloned().unwrap();
+                let result = json!([{ "value": value }]);
+                responses.send(Response::from_ok(id, result)).await.unwrap();
+            };
+
+            let (response, ()) = futures::join!(server, client);
+            assert_eq!(response, Ok(None));
+        }
+
+        assert_eq!(*settings.get(), json!({"value": 2}));
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [json!({"value": 1}), json!({"value": 2})]
+        );
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn serves_custom_requests() {
         let (mut service, _) = LspService::build(|_| Mock)
@@ -379,6 +866,184 @@ mod tests {
         assert_eq!(response, Ok(Some(ok)));
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn applies_layer_to_methods() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let (mut service, _) = LspService::build(|_| Mock)
+            .custom_method("custom", Mock::custom_request)
+            .layer(tower::layer::layer_fn(move |service: MethodService| {
+                let log = log.clone();
+                service.map_request(move |req: Request| {
+                    log.lock().unwrap().push(req.method().to_owned());
+                    req
+                })
+            }))
+            .finish();
+
+        let custom = Request::build("custom").params(123i32).id(1).finish();
+        let response = service.ready().await.unwrap().call(custom.clone()).await;
+        let not_initialized =
+            Response::from_error(1.into(), crate::jsonrpc::not_initialized_error());
+        assert_eq!(response, Ok(Some(not_initialized)));
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+
+        let response = service.ready().await.unwrap().call(custom).await;
+        let ok = Response::from_ok(1.into(), json!(123i32));
+        assert_eq!(response, Ok(Some(ok)));
+
+        assert_eq!(*seen.lock().unwrap(), ["initialize", "custom"]);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn reports_layer_errors() {
+        let (mut service, _) = LspService::build(|_| Mock)
+            .layer(tower::layer::layer_fn(|service: MethodService| {
+                service.map_result(|_| Err::<Option<Response>, BoxError>("rejected".into()))
+            }))
+            .finish();
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(initialize_request(1))
+            .await;
+        let error = Error {
+            code: ErrorCode::InternalError,
+            message: "rejected".into(),
+            data: None,
+        };
+        assert_eq!(response, Ok(Some(Response::from_error(1.into(), error))));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn applies_method_layer() {
+        let reject = || {
+            tower::layer::layer_fn(|service: MethodService| {
+                service.map_result(|_| Err::<Option<Response>, BoxError>("rejected".into()))
+            })
+        };
+
+        let builder = LspService::build(|_| Mock).custom_method("custom", Mock::custom_request);
+        let err = builder.method_layer("unknown", reject()).unwrap_err();
+        assert_eq!(err.method(), "unknown");
+
+        let (mut service, _) = LspService::build(|_| Mock)
+            .custom_method("custom", Mock::custom_request)
+            .method_layer("custom", reject())
+            .unwrap()
+            .method_layer("textDocument/hover", reject())
+            .unwrap()
+            .finish();
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        let ok = Response::from_ok(1.into(), json!({"capabilities":{}}));
+        assert_eq!(response, Ok(Some(ok)));
+
+        let error = Error {
+            code: ErrorCode::InternalError,
+            message: "rejected".into(),
+            data: None,
+        };
+
+        let custom = Request::build("custom").params(123i32).id(2).finish();
+        let response = service.ready().await.unwrap().call(custom).await;
+        let rejected = Response::from_error(2.into(), error.clone());
+        assert_eq!(response, Ok(Some(rejected)));
+
+        let hover = Request::build("textDocument/hover")
+            .params(
+                json!({"textDocument":{"uri":"file:///a.rs"},"position":{"line":0,"character":0}}),
+            )
+            .id(3)
+            .finish();
+        let response = service.ready().await.unwrap().call(hover).await;
+        let rejected = Response::from_error(3.into(), error);
+        assert_eq!(response, Ok(Some(rejected)));
+    }
+
+    #[derive(Debug, Default)]
+    struct Recorder {
+        gate: std::sync::Mutex<Option<futures::channel::oneshot::Receiver<()>>>,
+        log: std::sync::Mutex<Vec<&'static str>>,
+    }
+
+    #[async_trait]
+    impl LanguageServer for Recorder {
+        async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+            Ok(InitializeResult::default())
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Recorder {
+        async fn slow(&self) {
+            let gate = self.gate.lock().unwrap().take();
+            if let Some(rx) = gate {
+                rx.await.unwrap();
+            }
+
+            self.log.lock().unwrap().push("slow");
+        }
+
+        async fn fast(&self) {
+            self.log.lock().unwrap().push("fast");
+        }
+    }
+
+    async fn record_notifications(ordered: bool) -> Vec<&'static str> {
+        let (tx, rx) = futures::channel::oneshot::channel();
+        let (mut service, _) = LspService::build(|_| Recorder {
+            gate: std::sync::Mutex::new(Some(rx)),
+            ..Default::default()
+        })
+        .custom_method("slow", Recorder::slow)
+        .custom_method("fast", Recorder::fast)
+        .ordered_notifications(ordered)
+        .finish();
+
+        let initialize = initialize_request(1);
+        let response = service.ready().await.unwrap().call(initialize).await;
+        assert!(response.unwrap().unwrap().is_ok());
+
+        let slow = service
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::build("slow").finish());
+        let fast = service
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::build("fast").finish());
+
+        let fast = tokio::spawn(fast);
+        tokio::task::yield_now().await;
+        tx.send(()).unwrap();
+
+        let (slow, fast) = futures::join!(slow, fast);
+        assert_eq!(slow, Ok(None));
+        assert_eq!(fast.unwrap(), Ok(None));
+
+        let log = service.inner().log.lock().unwrap().clone();
+        log
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn orders_notifications() {
+        assert_eq!(record_notifications(true).await, ["slow", "fast"]);
+        assert_eq!(record_notifications(false).await, ["fast", "slow"]);
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn get_inner() {
         let (service, _) = LspService::build(|_| Mock).finish();
@@ -1,35 +1,67 @@
 //! Types for sending data to and from the language client.
 
+pub use self::progress::{PartialResults, ProgressReporter};
+pub use self::registration::RegistrationGuard;
+pub use self::settings::Settings;
 pub use self::socket::{ClientSocket, RequestStream, ResponseSink};
 
 use std::fmt::{self, Debug, Display, Formatter};
+use std::future::Future;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, Weak};
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use futures::channel::mpsc::{self, Sender};
-use futures::future::BoxFuture;
+use futures::future::{self, AbortHandle, BoxFuture, Either, FutureExt};
 use futures::sink::SinkExt;
+use futures_timer::Delay;
 use lsp_types::notification::*;
 use lsp_types::request::*;
 use lsp_types::*;
 use serde::Serialize;
 use serde_json::Value;
 use tower::Service;
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 
 use self::pending::Pending;
-use super::state::{ServerState, State};
+use self::progress::{PartialResult, PartialResultParams, Registry};
+use self::registration::Registrations;
+use self::settings::Section;
+use super::state::{ServerState, State, Trace};
 use super::ExitedError;
 use crate::jsonrpc::{self, Error, ErrorCode, Id, Request, Response};
+use crate::position::PositionEncoding;
 
 mod pending;
+mod progress;
+mod registration;
+mod settings;
 mod socket;
 
+/// Details about the client sent in the `initialize` request.
+#[derive(Debug, Default)]
+struct ClientDetails {
+    capabilities: Option<Arc<ClientCapabilities>>,
+    info: Option<ClientInfo>,
+    workspace_folders: Option<Vec<WorkspaceFolder>>,
+    root_uri: Option<Uri>,
+}
+
 struct ClientInner {
     tx: Sender<Request>,
     request_id: AtomicU32,
     pending: Arc<Pending>,
+    progress: Registry,
+    progress_id: AtomicU32,
+    registrations: Registrations,
+    registration_id: AtomicU32,
+    trace: Trace,
+    request_timeout: Mutex<Option<Duration>>,
+    position_encoding: Mutex<PositionEncoding>,
+    details: Mutex<ClientDetails>,
+    workspace_folders: Mutex<Vec<WorkspaceFolder>>,
+    settings: Mutex<Vec<Weak<dyn Section>>>,
     state: Arc<ServerState>,
 }
 
@@ -55,6 +87,16 @@ impl Client {
                 tx,
                 request_id: AtomicU32::new(0),
                 pending: pending.clone(),
+                progress: Registry::new(),
+                progress_id: AtomicU32::new(0),
+                registrations: Registrations::new(),
+                registration_id: AtomicU32::new(0),
+                trace: Trace::new(),
+                request_timeout: Mutex::new(None),
+                position_encoding: Mutex::new(PositionEncoding::Utf16),
+                details: Mutex::new(ClientDetails::default()),
+                workspace_folders: Mutex::new(Vec::new()),
+                settings: Mutex::new(Vec::new()),
                 state: state.clone(),
             }),
         };
@@ -73,6 +115,69 @@ impl Client {
     pub(crate) fn close(&self) {
         self.inner.tx.clone().close_channel();
     }
+
+    /// Stores the details about the client sent in the `initialize` request.
+    ///
+    /// This is called once the server has responded successfully to the request.
+    pub(crate) fn set_initialize_params(&self, params: InitializeParams) {
+        #[allow(deprecated)]
+        let root_uri = params.root_uri;
+
+        let folders = match (&params.workspace_folders, &root_uri) {
+            (Some(folders), _) => folders.clone(),
+            (None, Some(uri)) => vec![WorkspaceFolder {
+                uri: uri.clone(),
+                name: folder_name(uri),
+            }],
+            (None, None) => Vec::new(),
+        };
+        *self.inner.workspace_folders.lock().unwrap() = folders;
+
+        *self.inner.details.lock().unwrap() = ClientDetails {
+            capabilities: Some(Arc::new(params.capabilities)),
+            info: params.client_info,
+            workspace_folders: params.workspace_folders,
+            root_uri,
+        };
+    }
+
+    /// Applies the given changes to the tracked workspace folders.
+    ///
+    /// This is triggered by the `workspace/didChangeWorkspaceFolders` notification.
+    pub(crate) fn update_workspace_folders(&self, event: WorkspaceFoldersChangeEvent) {
+        let mut folders = self.inner.workspace_folders.lock().unwrap();
+        folders.retain(|f| !event.removed.iter().any(|r| r.uri == f.uri));
+        for added in event.added {
+            folders.retain(|f| f.uri != added.uri);
+            folders.push(added);
+        }
+    }
+
+    /// Registers cached [`Settings`] to be kept up to date with the client configuration.
+    pub(crate) fn register_settings(&self, settings: Weak<dyn Section>) {
+        self.inner.settings.lock().unwrap().push(settings);
+    }
+
+    /// Returns the registered [`Settings`] which are still alive, forgetting the dropped ones.
+    pub(crate) fn settings(&self) -> Vec<Arc<dyn Section>> {
+        let mut settings = self.inner.settings.lock().unwrap();
+        settings.retain(|section| section.strong_count() > 0);
+        settings.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Sets the trace level which controls whether [`Client::log_trace`] emits notifications.
+    ///
+    /// This is set by the `initialize` request and updated by the `$/setTrace` notification.
+    pub(crate) fn set_trace(&self, value: TraceValue) {
+        self.inner.trace.set(value);
+    }
+
+    /// Signals cancellation to the ongoing work done progress with the given token, if any.
+    ///
+    /// This is triggered by the `window/workDoneProgress/cancel` notification.
+    pub(crate) fn cancel_work_done_progress(&self, token: &ProgressToken) {
+        self.inner.progress.cancel(token);
+    }
 }
 
 impl Client {
@@ -80,6 +185,9 @@ impl Client {
 
     /// Registers a new capability with the client.
     ///
+    /// Consider using [`Client::register`] instead, which generates unique registration IDs,
+    /// records the registered capabilities and unregisters them automatically.
+    ///
     /// This corresponds to the [`client/registerCapability`] request.
     ///
     /// [`client/registerCapability`]: https://microsoft.github.io/language-server-protocol/specification#client_registerCapability
@@ -118,6 +226,76 @@ impl Client {
             .await
     }
 
+    /// Dynamically registers the given method with the client and returns a guard which
+    /// unregisters it when dropped.
+    ///
+    /// The registration is assigned a unique ID and recorded, so that all capabilities currently
+    /// registered can be listed with [`Client::registrations`]. The `register_options` are the
+    /// method-specific registration options, or `()` if the method takes none.
+    ///
+    /// This corresponds to the [`client/registerCapability`] request.
+    ///
+    /// [`client/registerCapability`]: https://microsoft.github.io/language-server-protocol/specification#client_registerCapability
+    ///
+    /// # Initialization
+    ///
+    /// If the request is sent to the client before the server has been initialized, this will
+    /// immediately return `Err` with JSON-RPC error code `-32002` ([read more]).
+    ///
+    /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    ///
+    /// # Client capabilities
+    ///
+    /// If the client does not declare `dynamicRegistration` for the feature the method belongs
+    /// to, this will immediately return an
+    /// [`Error::unsupported_by_client`] error.
+    pub async fn register<O: Serialize>(
+        &self,
+        method: impl Into<String>,
+        register_options: O,
+    ) -> jsonrpc::Result<RegistrationGuard> {
+        let method = method.into();
+        self.check_dynamic_registration(&method)?;
+
+        let register_options = match serde_json::to_value(register_options) {
+            Ok(Value::Null) => None,
+            Ok(value) => Some(value),
+            Err(err) => return Err(Error::invalid_params(err.to_string())),
+        };
+
+        let num = self.inner.registration_id.fetch_add(1, Ordering::Relaxed);
+        let registration = Registration {
+            id: format!("tower-lsp/registration/{num}"),
+            method: method.clone(),
+            register_options,
+        };
+
+        let id = registration.id.clone();
+        self.register_capability(vec![registration.clone()]).await?;
+        self.inner.registrations.insert(registration);
+
+        Ok(RegistrationGuard::new(self.clone(), id, method))
+    }
+
+    /// Asks the client to watch files matching the given patterns and returns a guard which stops
+    /// watching them when dropped.
+    ///
+    /// Changes to the watched files are reported to
+    /// [`LanguageServer::did_change_watched_files`](crate::LanguageServer::did_change_watched_files).
+    /// See [`Client::register`] for details.
+    pub async fn watch_files(
+        &self,
+        watchers: Vec<FileSystemWatcher>,
+    ) -> jsonrpc::Result<RegistrationGuard> {
+        let options = DidChangeWatchedFilesRegistrationOptions { watchers };
+        self.register(DidChangeWatchedFiles::METHOD, options).await
+    }
+
+    /// Returns all capabilities currently registered with [`Client::register`].
+    pub fn registrations(&self) -> Vec<Registration> {
+        self.inner.registrations.list()
+    }
+
     // Window Features
 
     /// Notifies the client to display a particular message in the user interface.
@@ -147,12 +325,15 @@ impl Client {
         message: M,
         actions: Option<Vec<MessageActionItem>>,
     ) -> jsonrpc::Result<Option<MessageActionItem>> {
-        self.send_request_unchecked::<ShowMessageRequest>(ShowMessageRequestParams {
+        let params = ShowMessageRequestParams {
             typ,
             message: message.to_string(),
             actions,
-        })
-        .await
+        };
+
+        // The user may take arbitrarily long to respond, so the default timeout does not apply.
+        self.send_request_unchecked::<ShowMessageRequest>(params, None)
+            .await
     }
 
     /// Notifies the client to log a particular message.
@@ -168,6 +349,48 @@ impl Client {
         .await;
     }
 
+    /// Returns the current trace level requested by the client.
+    ///
+    /// The initial value is taken from the `trace` field of the `initialize` request, and is
+    /// updated whenever the client sends a [`$/setTrace`] notification. Defaults to
+    /// [`TraceValue::Off`].
+    ///
+    /// [`$/setTrace`]: https://microsoft.github.io/language-server-protocol/specification#setTrace
+    pub fn trace_value(&self) -> TraceValue {
+        self.inner.trace.get()
+    }
+
+    /// Notifies the client to log a trace of the server's execution.
+    ///
+    /// The `verbose` information is only sent if the trace level is set to
+    /// [`TraceValue::Verbose`]. If the trace level is [`TraceValue::Off`], no notification is sent
+    /// at all.
+    ///
+    /// This corresponds to the [`$/logTrace`] notification.
+    ///
+    /// [`$/logTrace`]: https://microsoft.github.io/language-server-protocol/specification#logTrace
+    ///
+    /// # Initialization
+    ///
+    /// This notification will only be sent if the server is initialized.
+    ///
+    /// # Compatibility
+    ///
+    /// This notification was introduced in specification version 3.16.0.
+    pub async fn log_trace<M: Display>(&self, message: M, verbose: Option<String>) {
+        let verbose = match self.trace_value() {
+            TraceValue::Off => return,
+            TraceValue::Messages => None,
+            TraceValue::Verbose => verbose,
+        };
+
+        self.send_notification::<LogTrace>(LogTraceParams {
+            message: message.to_string(),
+            verbose,
+        })
+        .await;
+    }
+
     /// Asks the client to display a particular resource referenced by a URI in the user interface.
     ///
     /// Returns `Ok(true)` if the document was successfully shown, or `Ok(false)` otherwise.
@@ -186,13 +409,102 @@ impl Client {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.16.0.
+    ///
+    /// # Client capabilities
+    ///
+    /// If the client does not declare `window.showDocument.support`, this will immediately return
+    /// an [`Error::unsupported_by_client`] error.
     pub async fn show_document(&self, params: ShowDocumentParams) -> jsonrpc::Result<bool> {
         let response = self.send_request::<ShowDocument>(params).await?;
         Ok(response.success)
     }
 
-    // TODO: Add `work_done_progress_create()` here (since 3.15.0) when supported by `tower-lsp`.
-    // https://github.com/ebkalderon/tower-lsp/issues/176
+    /// Asks the client to create a work done progress with the given token.
+    ///
+    /// Progress can then be reported to the client using `$/progress` notifications bearing the
+    /// same token. Consider using [`Client::work_done_progress`] instead, which generates a unique
+    /// token and handles the notifications automatically.
+    ///
+    /// This corresponds to the [`window/workDoneProgress/create`] request.
+    ///
+    /// [`window/workDoneProgress/create`]: https://microsoft.github.io/language-server-protocol/specification#window_workDoneProgress_create
+    ///
+    /// # Initialization
+    ///
+    /// If the request is sent to the client before the server has been initialized, this will
+    /// immediately return `Err` with JSON-RPC error code `-32002` ([read more]).
+    ///
+    /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    ///
+    /// # Compatibility
+    ///
+    /// This request was introduced in specification version 3.15.0.
+    ///
+    /// # Client capabilities
+    ///
+    /// If the client does not declare `window.workDoneProgress`, this will immediately return
+    /// an [`Error::unsupported_by_client`] error.
+    pub async fn work_done_progress_create(&self, token: ProgressToken) -> jsonrpc::Result<()> {
+        self.send_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams { token })
+            .await
+    }
+
+    /// Creates a new server-initiated work done progress with a unique token and returns a
+    /// [`ProgressReporter`] handle to it.
+    ///
+    /// This sends a [`window/workDoneProgress/create`] request to the client. Once the client has
+    /// acknowledged it, progress can be reported through the returned handle.
+    ///
+    /// [`window/workDoneProgress/create`]: https://microsoft.github.io/language-server-protocol/specification#window_workDoneProgress_create
+    ///
+    /// # Initialization
+    ///
+    /// If the request is sent to the client before the server has been initialized, this will
+    /// immediately return `Err` with JSON-RPC error code `-32002` ([read more]).
+    ///
+    /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    ///
+    /// # Compatibility
+    ///
+    /// This request was introduced in specification version 3.15.0.
+    pub async fn work_done_progress(&self) -> jsonrpc::Result<ProgressReporter> {
+        let num = self.inner.progress_id.fetch_add(1, Ordering::Relaxed);
+        let token = ProgressToken::String(format!("tower-lsp/progress/{num}"));
+        self.work_done_progress_create(token.clone()).await?;
+        Ok(ProgressReporter::new(self.clone(), token))
+    }
+
+    /// Reports a partial result for a request to the client.
+    ///
+    /// The `token` is the `partialResultToken` provided by the client in the request params, and
+    /// `value` is a chunk of the request's result. Consider using [`Client::partial_results`]
+    /// instead, which handles requests with and without a token uniformly.
+    ///
+    /// This corresponds to the [`$/progress`] notification.
+    ///
+    /// [`$/progress`]: https://microsoft.github.io/language-server-protocol/specification#partialResults
+    ///
+    /// # Initialization
+    ///
+    /// This notification will only be sent if the server is initialized.
+    ///
+    /// # Compatibility
+    ///
+    /// This notification was introduced in specification version 3.15.0.
+    pub async fn send_partial_result<T: Serialize>(&self, token: ProgressToken, value: T) {
+        match PartialResultParams::new(token, value) {
+            Err(e) => error!("invalid JSON in partial result: {}", e),
+            Ok(params) => self.send_notification::<PartialResult>(params).await,
+        }
+    }
+
+    /// Creates a [`PartialResults`] handle for streaming the result of a request in chunks.
+    ///
+    /// The `token` is the `partialResultToken` provided by the client in the request params, if
+    /// any. See the [`PartialResults`] documentation for details.
+    pub fn partial_results<T: Serialize>(&self, token: Option<ProgressToken>) -> PartialResults<T> {
+        PartialResults::new(self.clone(), token)
+    }
 
     /// Notifies the client to log a telemetry event.
     ///
@@ -202,10 +514,12 @@ impl Client {
     pub async fn telemetry_event<S: Serialize>(&self, data: S) {
         match serde_json::to_value(data) {
             Err(e) => error!("invalid JSON in `telemetry/event` notification: {}", e),
-            Ok(mut value) => {
-                if !value.is_null() && !value.is_array() && !value.is_object() {
-                    value = Value::Array(vec![value]);
-                }
+            Ok(value) => {
+                let value = match value {
+                    Value::Object(value) => OneOf::Left(value),
+                    Value::Array(value) => OneOf::Right(value),
+                    value => OneOf::Right(vec![value]),
+                };
                 self.send_notification_unchecked::<TelemetryEvent>(value)
                     .await;
             }
@@ -235,6 +549,11 @@ impl Client {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.16.0.
+    ///
+    /// # Client capabilities
+    ///
+    /// If the client does not declare `workspace.codeLens.refreshSupport`, this will
+    /// immediately return an [`Error::unsupported_by_client`] error.
     pub async fn code_lens_refresh(&self) -> jsonrpc::Result<()> {
         self.send_request::<CodeLensRefresh>(()).await
     }
@@ -261,6 +580,11 @@ impl Client {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.16.0.
+    ///
+    /// # Client capabilities
+    ///
+    /// If the client does not declare `workspace.semanticTokens.refreshSupport`, this will
+    /// immediately return an [`Error::unsupported_by_client`] error.
     pub async fn semantic_tokens_refresh(&self) -> jsonrpc::Result<()> {
         self.send_request::<SemanticTokensRefresh>(()).await
     }
@@ -286,6 +610,11 @@ impl Client {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.17.0.
+    ///
+    /// # Client capabilities
+    ///
+    /// If the client does not declare `workspace.inlineValue.refreshSupport`, this will
+    /// immediately return an [`Error::unsupported_by_client`] error.
     pub async fn inline_value_refresh(&self) -> jsonrpc::Result<()> {
         self.send_request::<InlineValueRefreshRequest>(()).await
     }
@@ -311,6 +640,11 @@ impl Client {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.17.0.
+    ///
+    /// # Client capabilities
+    ///
+    /// If the client does not declare `workspace.inlayHint.refreshSupport`, this will
+    /// immediately return an [`Error::unsupported_by_client`] error.
     pub async fn inlay_hint_refresh(&self) -> jsonrpc::Result<()> {
         self.send_request::<InlayHintRefreshRequest>(()).await
     }
@@ -334,6 +668,11 @@ impl Client {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.17.0.
+    ///
+    /// # Client capabilities
+    ///
+    /// If the client does not declare `workspace.diagnostics.refreshSupport`, this will
+    /// immediately return an [`Error::unsupported_by_client`] error.
     pub async fn workspace_diagnostic_refresh(&self) -> jsonrpc::Result<()> {
         self.send_request::<WorkspaceDiagnosticRefresh>(()).await
     }
@@ -349,7 +688,7 @@ impl Client {
     /// This notification will only be sent if the server is initialized.
     pub async fn publish_diagnostics(
         &self,
-        uri: Url,
+        uri: Uri,
         diags: Vec<Diagnostic>,
         version: Option<i32>,
     ) {
@@ -382,6 +721,11 @@ impl Client {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.6.0.
+    ///
+    /// # Client capabilities
+    ///
+    /// If the client does not declare `workspace.configuration`, this will immediately return
+    /// an [`Error::unsupported_by_client`] error.
     pub async fn configuration(
         &self,
         items: Vec<ConfigurationItem>,
@@ -409,6 +753,11 @@ impl Client {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.6.0.
+    ///
+    /// # Client capabilities
+    ///
+    /// If the client does not declare `workspace.workspaceFolders`, this will immediately return
+    /// an [`Error::unsupported_by_client`] error.
     pub async fn workspace_folders(&self) -> jsonrpc::Result<Option<Vec<WorkspaceFolder>>> {
         self.send_request::<WorkspaceFoldersRequest>(()).await
     }
@@ -426,6 +775,11 @@ impl Client {
     /// immediately return `Err` with JSON-RPC error code `-32002` ([read more]).
     ///
     /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    ///
+    /// # Client capabilities
+    ///
+    /// If the client does not declare `workspace.applyEdit`, this will immediately return
+    /// an [`Error::unsupported_by_client`] error.
     pub async fn apply_edit(
         &self,
         edit: WorkspaceEdit,
@@ -451,6 +805,46 @@ impl Client {
         }
     }
 
+    /// Sends a notification to the client without waiting for room in the outgoing channel.
+    ///
+    /// This is intended for use in synchronous contexts, such as `Drop` implementations.
+    fn send_notification_nowait<N>(&self, params: N::Params)
+    where
+        N: lsp_types::notification::Notification,
+    {
+        let request = Request::from_notification::<N>(params);
+        if let State::Initialized | State::ShutDown = self.inner.state.get() {
+            if self.inner.tx.clone().try_send(request).is_err() {
+                error!("failed to send notification");
+            }
+        } else {
+            trace!("server not initialized, supressing message: {}", request);
+        }
+    }
+
+    /// Sends a request to the client without waiting for room in the outgoing channel, and
+    /// discards its response.
+    ///
+    /// This is intended for use in synchronous contexts, such as `Drop` implementations.
+    fn send_request_nowait<R>(&self, params: R::Params)
+    where
+        R: lsp_types::request::Request,
+    {
+        if let State::Initialized | State::ShutDown = self.inner.state.get() {
+            let id = self.next_request_id();
+            let request = Request::from_request::<R>(id.clone(), params);
+
+            // Register the request as pending, so that its response is silently discarded.
+            drop(self.inner.pending.wait(id.clone()));
+            if self.inner.tx.clone().try_send(request).is_err() {
+                self.inner.pending.cancel(&id);
+                error!("failed to send request");
+            }
+        } else {
+            trace!("server not initialized, supressing request: {}", R::METHOD);
+        }
+    }
+
     async fn send_notification_unchecked<N>(&self, params: N::Params)
     where
         N: lsp_types::notification::Notification,
@@ -463,18 +857,63 @@ impl Client {
 
     /// Sends a custom request to the client.
     ///
+    /// If the returned future is dropped before the client has responded, the request is
+    /// forgotten and a [`$/cancelRequest`] notification is sent to the client.
+    ///
+    /// [`$/cancelRequest`]: https://microsoft.github.io/language-server-protocol/specification#cancelRequest
+    ///
     /// # Initialization
     ///
     /// If the request is sent to the client before the server has been initialized, this will
     /// immediately return `Err` with JSON-RPC error code `-32002` ([read more]).
     ///
     /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    ///
+    /// # Timeout
+    ///
+    /// If a default request timeout was configured with [`LspServiceBuilder::request_timeout`]
+    /// and the client does not respond in time, this will return [`Error::request_timed_out`].
+    /// Use [`send_request_with_timeout`] to override the timeout.
+    ///
+    /// [`LspServiceBuilder::request_timeout`]: crate::LspServiceBuilder::request_timeout
+    /// [`send_request_with_timeout`]: Self::send_request_with_timeout
+    ///
+    /// # Client capabilities
+    ///
+    /// If `R` is a request defined by the specification which the client did not declare support
+    /// for in its [`ClientCapabilities`], this will immediately return an
+    /// [`Error::unsupported_by_client`] error instead of sending a request the client would reject.
     pub async fn send_request<R>(&self, params: R::Params) -> jsonrpc::Result<R::Result>
+    where
+        R: lsp_types::request::Request,
+    {
+        let timeout = self.request_timeout();
+        self.send_request_with_timeout::<R>(params, timeout).await
+    }
+
+    /// Sends a custom request to the client, waiting at most `timeout` for the response.
+    ///
+    /// If `timeout` is `None`, this waits for the response indefinitely, regardless of the default
+    /// request timeout. If the client does not respond in time, the request is cancelled as if the
+    /// future had been dropped and this will return [`Error::request_timed_out`].
+    ///
+    /// # Initialization
+    ///
+    /// If the request is sent to the client before the server has been initialized, this will
+    /// immediately return `Err` with JSON-RPC error code `-32002` ([read more]).
+    ///
+    /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    pub async fn send_request_with_timeout<R>(
+        &self,
+        params: R::Params,
+        timeout: Option<Duration>,
+    ) -> jsonrpc::Result<R::Result>
     where
         R: lsp_types::request::Request,
     {
         if let State::Initialized | State::ShutDown = self.inner.state.get() {
-            self.send_request_unchecked::<R>(params).await
+            self.check_supported(R::METHOD)?;
+            self.send_request_unchecked::<R>(params, timeout).await
         } else {
             let id = self.inner.request_id.load(Ordering::SeqCst) as i64 + 1;
             let msg = Request::from_request::<R>(id.into(), params);
@@ -483,14 +922,69 @@ impl Client {
         }
     }
 
-    async fn send_request_unchecked<R>(&self, params: R::Params) -> jsonrpc::Result<R::Result>
+    /// Sends a custom request to the client, returning a handle which can cancel it.
+    ///
+    /// Calling [`CancelHandle::cancel`] while the request is still in flight sends a
+    /// [`$/cancelRequest`] notification to the client and resolves the returned future to `Err`
+    /// with JSON-RPC error code `-32800`.
+    ///
+    /// [`$/cancelRequest`]: https://microsoft.github.io/language-server-protocol/specification#cancelRequest
+    ///
+    /// # Initialization
+    ///
+    /// If the request is sent to the client before the server has been initialized, the returned
+    /// future will immediately resolve to `Err` with JSON-RPC error code `-32002` ([read more]).
+    ///
+    /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    pub fn send_request_cancellable<R>(
+        &self,
+        params: R::Params,
+    ) -> (
+        impl Future<Output = jsonrpc::Result<R::Result>> + Send + 'static,
+        CancelHandle,
+    )
+    where
+        R: lsp_types::request::Request,
+    {
+        let client = self.clone();
+        let (fut, handle) =
+            future::abortable(async move { client.send_request::<R>(params).await });
+        let fut = fut.map(|result| result.unwrap_or_else(|_| Err(Error::request_cancelled())));
+        (fut, CancelHandle(handle))
+    }
+
+    async fn send_request_unchecked<R>(
+        &self,
+        params: R::Params,
+        timeout: Option<Duration>,
+    ) -> jsonrpc::Result<R::Result>
     where
         R: lsp_types::request::Request,
     {
         let id = self.next_request_id();
-        let request = Request::from_request::<R>(id, params);
+        let request = Request::from_request::<R>(id.clone(), params);
 
-        let response = match self.clone().call(request).await {
+        let guard = CancelOnDrop {
+            client: self,
+            id: Some(id),
+        };
+
+        let response_fut = self.clone().call(request);
+        let response = match timeout {
+            None => response_fut.await,
+            Some(duration) => match future::select(response_fut, Delay::new(duration)).await {
+                Either::Left((response, _)) => response,
+                Either::Right(_) => {
+                    warn!("client did not respond to {} in time", R::METHOD);
+                    drop(guard);
+                    return Err(Error::request_timed_out());
+                }
+            },
+        };
+
+        guard.disarm();
+
+        let response = match response {
             Ok(Some(response)) => response,
             Ok(None) | Err(_) => return Err(Error::internal_error()),
         };
@@ -507,6 +1001,123 @@ impl Client {
 }
 
 impl Client {
+    /// Returns the default timeout for server-to-client requests, if any.
+    pub fn request_timeout(&self) -> Option<Duration> {
+        *self.inner.request_timeout.lock().unwrap()
+    }
+
+    pub(crate) fn set_request_timeout(&self, timeout: Duration) {
+        *self.inner.request_timeout.lock().unwrap() = Some(timeout);
+    }
+
+    /// Returns the capabilities the client declared in the `initialize` request.
+    ///
+    /// Returns `None` if the server has not been initialized yet.
+    pub fn capabilities(&self) -> Option<Arc<ClientCapabilities>> {
+        self.inner.details.lock().unwrap().capabilities.clone()
+    }
+
+    /// Returns the name and version of the client sent in the `initialize` request, if any.
+    pub fn client_info(&self) -> Option<ClientInfo> {
+        self.inner.details.lock().unwrap().info.clone()
+    }
+
+    /// Returns the workspace folders sent in the `initialize` request, if any.
+    ///
+    /// Unlike [`Client::workspace_folders`], this does not send a request to the client.
+    pub fn initial_workspace_folders(&self) -> Option<Vec<WorkspaceFolder>> {
+        self.inner.details.lock().unwrap().workspace_folders.clone()
+    }
+
+    /// Returns the root URI of the workspace sent in the `initialize` request, if any.
+    pub fn root_uri(&self) -> Option<Uri> {
+        self.inner.details.lock().unwrap().root_uri.clone()
+    }
+
+    /// Returns the workspace folders currently open in the client.
+    ///
+    /// The folders are seeded from the `workspace_folders` sent in the `initialize` request, or
+    /// from its `root_uri` if the client does not support workspace folders, and kept up to date
+    /// with the `workspace/didChangeWorkspaceFolders` notifications received since. Unlike
+    /// [`Client::workspace_folders`], this does not send a request to the client.
+    ///
+    /// Note that clients only send these notifications if the server advertises
+    /// `workspace.workspace_folders.change_notifications` in its [`ServerCapabilities`].
+    pub fn current_workspace_folders(&self) -> Vec<WorkspaceFolder> {
+        self.inner.workspace_folders.lock().unwrap().clone()
+    }
+
+    /// Returns the workspace folder containing the document with the given URI, if any.
+    ///
+    /// If workspace folders are nested, the innermost one is returned. URIs are compared as
+    /// strings, so they should be normalized consistently by the client.
+    pub fn workspace_folder_of(&self, uri: &Uri) -> Option<WorkspaceFolder> {
+        let folders = self.inner.workspace_folders.lock().unwrap();
+        folders
+            .iter()
+            .filter(|folder| folder_contains(&folder.uri, uri))
+            .max_by_key(|folder| folder.uri.as_str().len())
+            .cloned()
+    }
+
+    /// Returns `Err` if the client declared that it does not support the given request method.
+    ///
+    /// Methods not defined by the specification and methods sent before the client capabilities
+    /// are known are always assumed to be supported.
+    fn check_supported(&self, method: &str) -> jsonrpc::Result<()> {
+        let capabilities = match self.capabilities() {
+            Some(capabilities) => capabilities,
+            None => return Ok(()),
+        };
+
+        if supports_request(&capabilities, method) {
+            Ok(())
+        } else {
+            let message = format!("client does not support `{}`", method);
+            Err(Error::unsupported_by_client(message))
+        }
+    }
+
+    /// Returns `Err` if the client declared that it does not support dynamic registration of the
+    /// given method.
+    fn check_dynamic_registration(&self, method: &str) -> jsonrpc::Result<()> {
+        let capabilities = match self.capabilities() {
+            Some(capabilities) => capabilities,
+            None => return Ok(()),
+        };
+
+        let flag = match registration::dynamic_registration_flag(method) {
+            Some(flag) => flag,
+            None => return Ok(()),
+        };
+
+        let capabilities = serde_json::to_value(&*capabilities).unwrap_or_default();
+        match capabilities.pointer(&flag) {
+            Some(Value::Bool(true)) => Ok(()),
+            _ => {
+                let message = format!(
+                    "client does not support dynamic registration of `{}`",
+                    method
+                );
+                Err(Error::unsupported_by_client(message))
+            }
+        }
+    }
+
+    /// Returns the position encoding negotiated during `initialize`.
+    ///
+    /// This is the encoding returned by the server in the `position_encoding` field of its
+    /// [`ServerCapabilities`], and defaults to [`PositionEncoding::Utf16`] if the server did not
+    /// specify one. See the [`position`](crate::position) module for conversions between
+    /// positions in this encoding and byte offsets.
+    pub fn position_encoding(&self) -> PositionEncoding {
+        *self.inner.position_encoding.lock().unwrap()
+    }
+
+    pub(crate) fn set_position_encoding(&self, encoding: PositionEncoding) {
+        *self.inner.position_encoding.lock().unwrap() = encoding;
+    }
+
     /// Increments the internal request ID counter and returns the previous value.
     ///
     /// This method can be used to build custom [`Request`] objects with numeric IDs that are
@@ -522,12 +1133,120 @@ impl Debug for Client {
         f.debug_struct("Client")
             .field("tx", &self.inner.tx)
             .field("pending", &self.inner.pending)
+            .field("progress", &self.inner.progress)
+            .field("registrations", &self.inner.registrations)
+            .field("trace", &self.inner.trace)
+            .field("request_timeout", &self.request_timeout())
+            .field("position_encoding", &self.position_encoding())
             .field("request_id", &self.inner.request_id)
             .field("state", &self.inner.state)
             .finish()
     }
 }
 
+/// Returns a display name for a workspace folder, i.e. the last segment of its path.
+fn folder_name(uri: &Uri) -> String {
+    let path = uri.as_str().trim_end_matches('/');
+    path.rsplit('/').next().unwrap_or(path).to_owned()
+}
+
+/// Returns `true` if `uri` is the URI of `folder` or of a file within it.
+fn folder_contains(folder: &Uri, uri: &Uri) -> bool {
+    let folder = folder.as_str().trim_end_matches('/');
+    match uri.as_str().strip_prefix(folder) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Returns whether the client declared support for the given server-to-client request method.
+fn supports_request(capabilities: &ClientCapabilities, method: &str) -> bool {
+    use lsp_types::request::Request as _;
+
+    let workspace = capabilities.workspace.as_ref();
+    let window = capabilities.window.as_ref();
+
+    let supported = match method {
+        ApplyWorkspaceEdit::METHOD => workspace.and_then(|w| w.apply_edit),
+        WorkspaceConfiguration::METHOD => workspace.and_then(|w| w.configuration),
+        WorkspaceFoldersRequest::METHOD => workspace.and_then(|w| w.workspace_folders),
+        CodeLensRefresh::METHOD => workspace
+            .and_then(|w| w.code_lens.as_ref())
+            .and_then(|c| c.refresh_support),
+        SemanticTokensRefresh::METHOD => workspace
+            .and_then(|w| w.semantic_tokens.as_ref())
+            .and_then(|c| c.refresh_support),
+        InlineValueRefreshRequest::METHOD => workspace
+            .and_then(|w| w.inline_value.as_ref())
+            .and_then(|c| c.refresh_support),
+        InlayHintRefreshRequest::METHOD => workspace
+            .and_then(|w| w.inlay_hint.as_ref())
+            .and_then(|c| c.refresh_support),
+        WorkspaceDiagnosticRefresh::METHOD => workspace
+            .and_then(|w| w.diagnostic.as_ref())
+            .and_then(|c| c.refresh_support),
+        ShowDocument::METHOD => window
+            .and_then(|w| w.show_document.as_ref())
+            .map(|c| c.support),
+        WorkDoneProgressCreate::METHOD => window.and_then(|w| w.work_done_progress),
+        _ => return true,
+    };
+
+    supported.unwrap_or(false)
+}
+
+/// Forgets a pending server-to-client request and notifies the client if it is dropped before
+/// being disarmed.
+struct CancelOnDrop<'a> {
+    client: &'a Client,
+    id: Option<Id>,
+}
+
+impl CancelOnDrop<'_> {
+    fn disarm(mut self) {
+        self.id = None;
+    }
+}
+
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        let id = match self.id.take() {
+            Some(id) => id,
+            None => return,
+        };
+
+        self.client.inner.pending.cancel(&id);
+
+        let id = match id {
+            Id::Number(num) => match i32::try_from(num) {
+                Ok(num) => NumberOrString::Number(num),
+                Err(_) => NumberOrString::String(num.to_string()),
+            },
+            Id::String(s) => NumberOrString::String(s),
+            Id::Null => return,
+        };
+
+        self.client
+            .send_notification_nowait::<Cancel>(CancelParams { id });
+    }
+}
+
+/// Handle for cancelling an in-flight server-to-client request.
+///
+/// This handle is created with [`Client::send_request_cancellable`].
+#[derive(Clone, Debug)]
+pub struct CancelHandle(AbortHandle);
+
+impl CancelHandle {
+    /// Cancels the corresponding request, if it is still in flight.
+    ///
+    /// The client is notified with a `$/cancelRequest` notification. If the request has already
+    /// completed, this method call will do nothing.
+    pub fn cancel(&self) {
+        self.0.abort();
+    }
+}
+
 impl Service<Request> for Client {
     type Response = Option<Response>;
     type Error = ExitedError;
@@ -604,29 +1323,285 @@ mod tests {
         assert_client_message(|p| async move { p.show_message(typ, msg).await }, expected).await;
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_unsupported_requests() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+        let (client, socket) = Client::new(state);
+
+        let params: InitializeParams = serde_json::from_value(json!({
+            "capabilities": {"workspace": {"inlayHint": {"refreshSupport": false}}},
+            "clientInfo": {"name": "foo"},
+            "rootUri": "file:///foo",
+        }))
+        .unwrap();
+        client.set_initialize_params(params);
+
+        assert!(client.capabilities().is_some());
+        assert_eq!(client.client_info().unwrap().name, "foo");
+        assert_eq!(client.root_uri().unwrap().as_str(), "file:///foo");
+        assert_eq!(client.initial_workspace_folders(), None);
+
+        let err = client.inlay_hint_refresh().await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::ServerError(-32098));
+        let err = client.show_document(ShowDocumentParams {
+            uri: "file:///foo/bar.rs".parse().unwrap(),
+            external: None,
+            take_focus: None,
+            selection: None,
+        });
+        assert_eq!(err.await.unwrap_err().code, ErrorCode::ServerError(-32098));
+
+        // Requests not defined by the specification are always sent.
+        enum Custom {}
+        impl lsp_types::request::Request for Custom {
+            type Params = ();
+            type Result = ();
+            const METHOD: &'static str = "custom/request";
+        }
+
+        let mut fut = Box::pin(client.send_request::<Custom>(()));
+        assert!(futures::poll!(fut.as_mut()).is_pending());
+        drop(fut);
+        drop(client);
+
+        let messages: Vec<_> = socket.collect().await;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].method(), "custom/request");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn registers_capabilities() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+        let (client, socket) = Client::new(state);
+        let (mut requests, mut responses) = socket.split();
+
+        let params: InitializeParams = serde_json::from_value(json!({
+            "capabilities": {"workspace": {"didChangeWatchedFiles": {"dynamicRegistration": true}}},
+        }))
+        .unwrap();
+        client.set_initialize_params(params);
+
+        let server = async move {
+            let err = client.register("textDocument/hover", ()).await.unwrap_err();
+            assert_eq!(err.code, ErrorCode::ServerError(-32098));
+
+            let watcher = FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*.rs".into()),
+                kind: None,
+            };
+            let guard = client.watch_files(vec![watcher]).await.unwrap();
+            assert_eq!(guard.method(), DidChangeWatchedFiles::METHOD);
+            assert_eq!(client.registrations().len(), 1);
+
+            drop(guard);
+            assert!(client.registrations().is_empty());
+        };
+
+        let client = async move {
+            let register = requests.next().await.unwrap();
+            let id = register.id().cloned().unwrap();
+            responses
+                .send(Response::from_ok(id, json!(null)))
+                .await
+                .unwrap();
+            (register, requests.collect::<Vec<_>>().await)
+        };
+
+        let ((), (register, messages)) = futures::join!(server, client);
+        assert_eq!(register.method(), "client/registerCapability");
+
+        let params = UnregistrationParams {
+            unregisterations: vec![Unregistration {
+                id: "tower-lsp/registration/0".into(),
+                method: DidChangeWatchedFiles::METHOD.into(),
+            }],
+        };
+        let expected = Request::from_request::<UnregisterCapability>(Id::Number(1), params);
+        assert_eq!(messages, vec![expected]);
+    }
+
+    #[test]
+    fn tracks_workspace_folders() {
+        let state = Arc::new(ServerState::new());
+        let (client, _) = Client::new(state);
+
+        let folder = |uri: &str| WorkspaceFolder {
+            uri: uri.parse().unwrap(),
+            name: folder_name(&uri.parse().unwrap()),
+        };
+
+        let params: InitializeParams = serde_json::from_value(json!({
+            "capabilities": {},
+            "rootUri": "file:///ignored",
+            "workspaceFolders": [{"uri": "file:///a", "name": "a"}],
+        }))
+        .unwrap();
+        client.set_initialize_params(params);
+        assert_eq!(
+            client.current_workspace_folders(),
+            vec![folder("file:///a")]
+        );
+
+        client.update_workspace_folders(WorkspaceFoldersChangeEvent {
+            added: vec![folder("file:///a/b/"), folder("file:///c")],
+            removed: vec![],
+        });
+
+        let folder_of = |uri: &str| client.workspace_folder_of(&uri.parse().unwrap());
+        assert_eq!(folder_of("file:///a/x.rs"), Some(folder("file:///a")));
+        assert_eq!(folder_of("file:///a/b/x.rs"), Some(folder("file:///a/b/")));
+        assert_eq!(folder_of("file:///a/bx.rs"), Some(folder("file:///a")));
+        assert_eq!(folder_of("file:///c"), Some(folder("file:///c")));
+        assert_eq!(folder_of("file:///d/x.rs"), None);
+
+        client.update_workspace_folders(WorkspaceFoldersChangeEvent {
+            added: vec![],
+            removed: vec![folder("file:///a")],
+        });
+        assert_eq!(folder_of("file:///a/x.rs"), None);
+        assert_eq!(folder_of("file:///a/b/x.rs"), Some(folder("file:///a/b/")));
+        assert_eq!(folder("file:///a/b/").name, "b");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn cancels_dropped_request() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+        let (client, socket) = Client::new(state);
+
+        let mut fut = Box::pin(client.send_request::<WorkspaceFoldersRequest>(()));
+        assert!(futures::poll!(fut.as_mut()).is_pending());
+        drop(fut);
+
+        assert_eq!(format!("{:?}", client.inner.pending), "{}");
+        drop(client);
+
+        let messages: Vec<_> = socket.collect().await;
+        let expected = vec![
+            Request::from_request::<WorkspaceFoldersRequest>(Id::Number(0), ()),
+            Request::from_notification::<Cancel>(CancelParams {
+                id: NumberOrString::Number(0),
+            }),
+        ];
+        assert_eq!(messages, expected);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn cancels_request_with_handle() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+        let (client, socket) = Client::new(state);
+
+        let (fut, handle) = client.send_request_cancellable::<WorkspaceFoldersRequest>(());
+        let mut fut = Box::pin(fut);
+        assert!(futures::poll!(fut.as_mut()).is_pending());
+
+        handle.cancel();
+        assert_eq!(fut.await, Err(Error::request_cancelled()));
+        drop(client);
+
+        let messages: Vec<_> = socket.collect().await;
+        let expected = vec![
+            Request::from_request::<WorkspaceFoldersRequest>(Id::Number(0), ()),
+            Request::from_notification::<Cancel>(CancelParams {
+                id: NumberOrString::Number(0),
+            }),
+        ];
+        assert_eq!(messages, expected);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn times_out_request() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+        let (client, socket) = Client::new(state);
+        client.set_request_timeout(Duration::from_millis(10));
+
+        let server = async move {
+            let result = client.send_request::<WorkspaceFoldersRequest>(()).await;
+            assert_eq!(result, Err(Error::request_timed_out()));
+
+            let timeout = Some(Duration::from_millis(1));
+            let result = client
+                .send_request_with_timeout::<CodeLensRefresh>((), timeout)
+                .await;
+            assert_eq!(result, Err(Error::request_timed_out()));
+
+            assert_eq!(format!("{:?}", client.inner.pending), "{}");
+        };
+
+        let ((), messages) = futures::join!(server, socket.collect::<Vec<_>>());
+        let expected = vec![
+            Request::from_request::<WorkspaceFoldersRequest>(Id::Number(0), ()),
+            Request::from_notification::<Cancel>(CancelParams {
+                id: NumberOrString::Number(0),
+            }),
+            Request::from_request::<CodeLensRefresh>(Id::Number(1), ()),
+            Request::from_notification::<Cancel>(CancelParams {
+                id: NumberOrString::Number(1),
+            }),
+        ];
+        assert_eq!(messages, expected);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn log_trace() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+        let (client, socket) = Client::new(state);
+
+        let server = async move {
+            client.log_trace("off", Some("ignored".into())).await;
+
+            client.set_trace(TraceValue::Messages);
+            client.log_trace("messages", Some("ignored".into())).await;
+
+            client.set_trace(TraceValue::Verbose);
+            client.log_trace("verbose", Some("details".into())).await;
+        };
+
+        let ((), messages) = futures::join!(server, socket.collect::<Vec<_>>());
+        let expected = vec![
+            Request::from_notification::<LogTrace>(LogTraceParams {
+                message: "messages".into(),
+                verbose: None,
+            }),
+            Request::from_notification::<LogTrace>(LogTraceParams {
+                message: "verbose".into(),
+                verbose: Some("details".into()),
+            }),
+        ];
+        assert_eq!(messages, expected);
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn telemetry_event() {
         let null = json!(null);
-        let expected = Request::from_notification::<TelemetryEvent>(null.clone());
+        let wrapped = OneOf::Right(vec![null.clone()]);
+        let expected = Request::from_notification::<TelemetryEvent>(wrapped);
         assert_client_message(|p| async move { p.telemetry_event(null).await }, expected).await;
 
         let array = json!([1, 2, 3]);
-        let expected = Request::from_notification::<TelemetryEvent>(array.clone());
+        let wrapped = OneOf::Right(vec![json!(1), json!(2), json!(3)]);
+        let expected = Request::from_notification::<TelemetryEvent>(wrapped);
         assert_client_message(|p| async move { p.telemetry_event(array).await }, expected).await;
 
         let object = json!({});
-        let expected = Request::from_notification::<TelemetryEvent>(object.clone());
+        let wrapped = OneOf::Left(serde_json::Map::new());
+        let expected = Request::from_notification::<TelemetryEvent>(wrapped);
         assert_client_message(|p| async move { p.telemetry_event(object).await }, expected).await;
 
         let other = json!("hello");
-        let wrapped = Value::Array(vec![other.clone()]);
+        let wrapped = OneOf::Right(vec![other.clone()]);
         let expected = Request::from_notification::<TelemetryEvent>(wrapped);
         assert_client_message(|p| async move { p.telemetry_event(other).await }, expected).await;
     }
 
     #[tokio::test(flavor = "current_thread")]
     async fn publish_diagnostics() {
-        let uri: Url = "file:///path/to/file".parse().unwrap();
+        let uri: Uri = "file:///path/to/file".parse().unwrap();
         let diagnostics = vec![Diagnostic::new_simple(Default::default(), "example".into())];
 
         let params = PublishDiagnosticsParams::new(uri.clone(), diagnostics.clone(), None);
@@ -638,4 +1613,100 @@ mod tests {
         )
         .await;
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn partial_results() {
+        let token = ProgressToken::String("foo".into());
+        let expected = Request::build("$/progress")
+            .params(json!({"token":"foo","value":[1,2]}))
+            .finish();
+
+        assert_client_message(
+            |p| async move {
+                let mut results = p.partial_results(Some(token));
+                results.send(vec![1, 2]).await;
+                results.send(Vec::new()).await;
+                assert_eq!(results.finish(), Vec::<i32>::new());
+            },
+            expected,
+        )
+        .await;
+
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+        let (client, socket) = Client::new(state);
+
+        let mut results = client.partial_results(None);
+        results.send(vec![1, 2]).await;
+        results.send(vec![3]).await;
+        assert_eq!(results.finish(), vec![1, 2, 3]);
+
+        drop(client);
+        assert_eq!(socket.collect::<Vec<_>>().await, vec![]);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn work_done_progress() {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+
+        let (client, socket) = Client::new(state);
+        let (mut requests, mut responses) = socket.split();
+
+        let server = async move {
+            let progress = client.work_done_progress().await.unwrap();
+            progress.begin("Indexing", true).await;
+            progress.report(Some(50), Some("1/2".into())).await;
+
+            assert!(!progress.is_cancelled());
+            client.cancel_work_done_progress(progress.token());
+            assert!(progress.is_cancelled());
+            progress.cancelled().await;
+
+            progress.token().clone()
+        };
+
+        let client = async move {
+            let create = requests.next().await.unwrap();
+            let id = create.id().cloned().unwrap();
+            responses
+                .send(Response::from_ok(id, json!(null)))
+                .await
+                .unwrap();
+            (create, requests.collect::<Vec<_>>().await)
+        };
+
+        let (token, (create, messages)) = futures::join!(server, client);
+
+        let params = WorkDoneProgressCreateParams {
+            token: token.clone(),
+        };
+        assert_eq!(
+            create,
+            Request::from_request::<WorkDoneProgressCreate>(Id::Number(0), params)
+        );
+
+        let progress = |value| {
+            Request::from_notification::<Progress>(ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            })
+        };
+
+        let expected = vec![
+            progress(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: "Indexing".into(),
+                cancellable: Some(true),
+                message: None,
+                percentage: None,
+            })),
+            progress(WorkDoneProgress::Report(WorkDoneProgressReport {
+                cancellable: None,
+                message: Some("1/2".into()),
+                percentage: Some(50),
+            })),
+            progress(WorkDoneProgress::End(WorkDoneProgressEnd { message: None })),
+        ];
+        assert_eq!(messages, expected);
+    }
 }
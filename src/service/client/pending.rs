@@ -5,7 +5,7 @@ use std::future::Future;
 
 use dashmap::{mapref::entry::Entry, DashMap};
 use futures::channel::oneshot;
-use tracing::warn;
+use tracing::{debug, warn};
 
 use crate::jsonrpc::{Id, Response};
 
@@ -32,7 +32,9 @@ impl Pending {
                         _ => entry.get_mut().remove(0),
                     };
 
-                    tx.send(r).expect("receiver already dropped");
+                    if let Err(r) = tx.send(r) {
+                        debug!("waiter for request {} was dropped, ignoring", r.id());
+                    }
                 }
             },
         }
@@ -59,6 +61,13 @@ impl Pending {
 
         async { rx.await.expect("sender already dropped") }
     }
+
+    /// Stops waiting for the response to the given request ID, if any.
+    ///
+    /// If the response arrives afterwards, it will be discarded with a warning.
+    pub fn cancel(&self, id: &Id) {
+        self.0.remove(id);
+    }
 }
 
 impl Debug for Pending {
@@ -110,4 +119,17 @@ mod tests {
         assert_eq!(wait_fut1.await, bar);
         assert_eq!(wait_fut2.await, foo);
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn discards_cancelled_response() {
+        let pending = Pending::new();
+
+        let id = Id::Number(1);
+        let wait_fut = pending.wait(id.clone());
+        pending.cancel(&id);
+        drop(wait_fut);
+
+        pending.insert(Response::from_ok(id, json!({})));
+        assert_eq!(format!("{:?}", pending), "{}");
+    }
 }
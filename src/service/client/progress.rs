@@ -0,0 +1,350 @@
+//! Types for reporting work done progress and partial results to the language client.
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use futures::channel::oneshot;
+use futures::future::{self, FutureExt, Shared};
+use lsp_types::notification::{Notification, Progress};
+use lsp_types::{
+    ProgressParams, ProgressParamsValue, ProgressToken, WorkDoneProgress, WorkDoneProgressBegin,
+    WorkDoneProgressEnd, WorkDoneProgressReport,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{debug, info};
+
+use super::Client;
+
+/// A hashmap containing the cancellation signals of all ongoing work done progress operations,
+/// keyed by progress token.
+pub struct Registry(DashMap<ProgressToken, Signal>);
+
+struct Signal {
+    cancelled: Arc<AtomicBool>,
+    tx: oneshot::Sender<()>,
+}
+
+impl Registry {
+    /// Creates a new work done progress registry.
+    pub fn new() -> Self {
+        Registry(DashMap::new())
+    }
+
+    /// Registers a new progress token and returns its corresponding cancellation signal.
+    fn insert(&self, token: ProgressToken) -> Cancellation {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = oneshot::channel();
+
+        let signal = Signal {
+            cancelled: cancelled.clone(),
+            tx,
+        };
+
+        self.0.insert(token, signal);
+        Cancellation {
+            cancelled,
+            rx: rx.shared(),
+        }
+    }
+
+    /// Forgets the given progress token, if it exists.
+    fn remove(&self, token: &ProgressToken) {
+        self.0.remove(token);
+    }
+
+    /// Signals cancellation to the progress operation corresponding to this token.
+    ///
+    /// If no such progress operation is ongoing, this method call will do nothing.
+    pub fn cancel(&self, token: &ProgressToken) {
+        if let Some((_, signal)) = self.0.remove(token) {
+            signal.cancelled.store(true, Ordering::SeqCst);
+            let _ = signal.tx.send(());
+            info!("successfully cancelled progress with token: {:?}", token);
+        } else {
+            debug!(
+                "client asked to cancel progress {:?}, but no such progress exists, ignoring",
+                token
+            );
+        }
+    }
+}
+
+impl Debug for Registry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_set()
+            .entries(self.0.iter().map(|entry| entry.key().clone()))
+            .finish()
+    }
+}
+
+struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+    rx: Shared<oneshot::Receiver<()>>,
+}
+
+/// Handle for reporting server-initiated work done progress to the client.
+///
+/// This handle is created with [`Client::work_done_progress`]. The progress is first started
+/// with [`begin`](Self::begin), updated any number of times with [`report`](Self::report), and
+/// finally ended with [`finish`](Self::finish).
+///
+/// If the handle is dropped after the progress has begun but before it was finished, the progress
+/// is ended automatically.
+///
+/// # Cancellation
+///
+/// If the progress was started as cancellable and the user cancels it in the client, the client
+/// sends a [`window/workDoneProgress/cancel`] notification to the server. This is routed to the
+/// corresponding handle, which can be observed with [`is_cancelled`](Self::is_cancelled) and
+/// [`cancelled`](Self::cancelled).
+///
+/// [`window/workDoneProgress/cancel`]: https://microsoft.github.io/language-server-protocol/specification#window_workDoneProgress_cancel
+pub struct ProgressReporter {
+    client: Client,
+    token: ProgressToken,
+    cancellation: Cancellation,
+    begun: AtomicBool,
+    finished: bool,
+}
+
+impl ProgressReporter {
+    pub(super) fn new(client: Client, token: ProgressToken) -> Self {
+        let cancellation = client.inner.progress.insert(token.clone());
+
+        ProgressReporter {
+            client,
+            token,
+            cancellation,
+            begun: AtomicBool::new(false),
+            finished: false,
+        }
+    }
+
+    /// Returns the progress token identifying this progress operation.
+    pub fn token(&self) -> &ProgressToken {
+        &self.token
+    }
+
+    /// Starts the progress operation with the given `title`.
+    ///
+    /// If `cancellable` is `true`, the client may show a cancel button to the user.
+    ///
+    /// This corresponds to the `$/progress` notification with a [`WorkDoneProgressBegin`] value.
+    pub async fn begin<T: Display>(&self, title: T, cancellable: bool) {
+        self.begun.store(true, Ordering::SeqCst);
+        self.send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title: title.to_string(),
+            cancellable: Some(cancellable),
+            message: None,
+            percentage: None,
+        }))
+        .await;
+    }
+
+    /// Reports the current progress to the client.
+    ///
+    /// The `percentage` is expected to be in the range `0..=100`. If either value is `None`, the
+    /// previously reported value (if any) remains valid.
+    ///
+    /// This corresponds to the `$/progress` notification with a [`WorkDoneProgressReport`] value.
+    pub async fn report(&self, percentage: Option<u32>, message: Option<String>) {
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: None,
+            message,
+            percentage,
+        }))
+        .await;
+    }
+
+    /// Ends the progress operation, optionally displaying a final `message` to the user.
+    ///
+    /// This corresponds to the `$/progress` notification with a [`WorkDoneProgressEnd`] value.
+    pub async fn finish(mut self, message: Option<String>) {
+        self.finished = true;
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd { message }))
+            .await;
+    }
+
+    /// Returns `true` if the client has requested cancellation of this progress operation.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the client requests cancellation of this progress operation.
+    ///
+    /// If the client never cancels the progress, the returned future will never resolve.
+    pub async fn cancelled(&self) {
+        if self.cancellation.rx.clone().await.is_err() {
+            future::pending::<()>().await;
+        }
+    }
+
+    async fn send(&self, value: WorkDoneProgress) {
+        let params = ProgressParams {
+            token: self.token.clone(),
+            value: ProgressParamsValue::WorkDone(value),
+        };
+
+        self.client.send_notification::<Progress>(params).await;
+    }
+}
+
+impl Debug for ProgressReporter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("ProgressReporter")
+            .field("token", &self.token)
+            .field("begun", &self.begun)
+            .field("finished", &self.finished)
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        self.client.inner.progress.remove(&self.token);
+
+        if self.begun.load(Ordering::SeqCst) && !self.finished {
+            let params = ProgressParams {
+                token: self.token.clone(),
+                value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(WorkDoneProgressEnd {
+                    message: None,
+                })),
+            };
+
+            self.client.send_notification_nowait::<Progress>(params);
+        }
+    }
+}
+
+/// Parameters of a `$/progress` notification carrying a partial result.
+///
+/// Unlike [`ProgressParams`], the value may be of any shape, since it depends on the request whose
+/// results are being streamed.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PartialResultParams {
+    token: ProgressToken,
+    value: Value,
+}
+
+/// The `$/progress` notification, used for reporting partial results.
+pub enum PartialResult {}
+
+impl Notification for PartialResult {
+    type Params = PartialResultParams;
+    const METHOD: &'static str = Progress::METHOD;
+}
+
+impl PartialResultParams {
+    /// Creates the partial result notification parameters for the given progress token.
+    pub fn new<T: Serialize>(token: ProgressToken, value: T) -> serde_json::Result<Self> {
+        let value = serde_json::to_value(value)?;
+        Ok(PartialResultParams { token, value })
+    }
+}
+
+/// Handle for streaming the results of a request to the client in chunks.
+///
+/// Requests such as [`textDocument/references`], [`textDocument/documentSymbol`] and
+/// [`workspace/symbol`] may carry a `partialResultToken` in their params. If the client provided
+/// such a token, every chunk passed to [`send`](Self::send) is immediately reported to the client
+/// as a `$/progress` notification, and [`finish`](Self::finish) returns an empty list, which the
+/// handler should then return as its final response, as required by the specification.
+///
+/// If the client did not provide a token, the chunks are buffered instead and returned all at
+/// once by [`finish`](Self::finish), so handlers can be written the same way in both cases.
+///
+/// This handle is created with [`Client::partial_results`].
+///
+/// [`textDocument/references`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_references
+/// [`textDocument/documentSymbol`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_documentSymbol
+/// [`workspace/symbol`]: https://microsoft.github.io/language-server-protocol/specification#workspace_symbol
+///
+/// # Example
+///
+/// ```rust
+/// # use tower_lsp::jsonrpc::Result;
+/// # use tower_lsp::lsp_types::*;
+/// # use tower_lsp::Client;
+/// #
+/// # struct Backend {
+/// #     client: Client,
+/// # }
+/// #
+/// # impl Backend {
+/// #     async fn find_references(&self, _: &ReferenceParams) -> Vec<Vec<Location>> {
+/// #         Vec::new()
+/// #     }
+/// #
+/// async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+///     let token = params.partial_result_params.partial_result_token.clone();
+///     let mut results = self.client.partial_results(token);
+///
+///     for chunk in self.find_references(&params).await {
+///         results.send(chunk).await;
+///     }
+///
+///     Ok(Some(results.finish()))
+/// }
+/// # }
+/// ```
+pub struct PartialResults<T> {
+    client: Client,
+    token: Option<ProgressToken>,
+    buffer: Vec<T>,
+}
+
+impl<T: Serialize> PartialResults<T> {
+    pub(super) fn new(client: Client, token: Option<ProgressToken>) -> Self {
+        PartialResults {
+            client,
+            token,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Returns the partial result token provided by the client, if any.
+    pub fn token(&self) -> Option<&ProgressToken> {
+        self.token.as_ref()
+    }
+
+    /// Returns `true` if results are streamed to the client, rather than buffered.
+    pub fn is_streaming(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Sends a chunk of results to the client, or buffers it if the client did not provide a
+    /// partial result token.
+    ///
+    /// Empty chunks are ignored.
+    pub async fn send(&mut self, chunk: Vec<T>) {
+        if chunk.is_empty() {
+            return;
+        }
+
+        match &self.token {
+            Some(token) => self.client.send_partial_result(token.clone(), chunk).await,
+            None => self.buffer.extend(chunk),
+        }
+    }
+
+    /// Returns the final response value for the request.
+    ///
+    /// This is empty if the results were streamed to the client, or contains all the buffered
+    /// chunks otherwise.
+    pub fn finish(self) -> Vec<T> {
+        self.buffer
+    }
+}
+
+impl<T> Debug for PartialResults<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("PartialResults")
+            .field("token", &self.token)
+            .field("buffered", &self.buffer.len())
+            .finish()
+    }
+}
@@ -0,0 +1,185 @@
+//! Types for dynamically registering capabilities with the language client.
+
+use std::fmt::{self, Debug, Formatter};
+
+use dashmap::DashMap;
+use lsp_types::request::UnregisterCapability;
+use lsp_types::{Registration, Unregistration, UnregistrationParams};
+
+use super::Client;
+use crate::jsonrpc;
+
+/// A hashmap containing all capabilities currently registered with the client, keyed by
+/// registration ID.
+pub struct Registrations(DashMap<String, Registration>);
+
+impl Registrations {
+    /// Creates a new capability registry.
+    pub fn new() -> Self {
+        Registrations(DashMap::new())
+    }
+
+    /// Records the given registration.
+    pub fn insert(&self, registration: Registration) {
+        self.0.insert(registration.id.clone(), registration);
+    }
+
+    /// Forgets the registration with the given ID, if it exists.
+    fn remove(&self, id: &str) {
+        self.0.remove(id);
+    }
+
+    /// Returns all recorded registrations.
+    pub fn list(&self) -> Vec<Registration> {
+        self.0.iter().map(|entry| entry.value().clone()).collect()
+    }
+}
+
+impl Debug for Registrations {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.0
+                    .iter()
+                    .map(|e| (e.key().clone(), e.value().method.clone())),
+            )
+            .finish()
+    }
+}
+
+/// Returns the JSON pointer to the `dynamicRegistration` flag in the client capabilities which
+/// governs the given method, if any.
+pub fn dynamic_registration_flag(method: &str) -> Option<String> {
+    let feature = match method {
+        "textDocument/didOpen"
+        | "textDocument/didChange"
+        | "textDocument/didClose"
+        | "textDocument/willSave"
+        | "textDocument/willSaveWaitUntil"
+        | "textDocument/didSave" => "textDocument/synchronization",
+        "textDocument/documentColor" => "textDocument/colorProvider",
+        "textDocument/prepareCallHierarchy" => "textDocument/callHierarchy",
+        "textDocument/prepareTypeHierarchy" => "textDocument/typeHierarchy",
+        "textDocument/completion"
+        | "textDocument/hover"
+        | "textDocument/signatureHelp"
+        | "textDocument/declaration"
+        | "textDocument/definition"
+        | "textDocument/typeDefinition"
+        | "textDocument/implementation"
+        | "textDocument/references"
+        | "textDocument/documentHighlight"
+        | "textDocument/documentSymbol"
+        | "textDocument/codeAction"
+        | "textDocument/codeLens"
+        | "textDocument/documentLink"
+        | "textDocument/formatting"
+        | "textDocument/rangeFormatting"
+        | "textDocument/onTypeFormatting"
+        | "textDocument/rename"
+        | "textDocument/foldingRange"
+        | "textDocument/selectionRange"
+        | "textDocument/linkedEditingRange"
+        | "textDocument/semanticTokens"
+        | "textDocument/moniker"
+        | "textDocument/inlineValue"
+        | "textDocument/inlayHint"
+        | "textDocument/diagnostic"
+        | "workspace/didChangeConfiguration"
+        | "workspace/didChangeWatchedFiles"
+        | "workspace/symbol"
+        | "workspace/executeCommand" => method,
+        "workspace/willCreateFiles"
+        | "workspace/didCreateFiles"
+        | "workspace/willRenameFiles"
+        | "workspace/didRenameFiles"
+        | "workspace/willDeleteFiles"
+        | "workspace/didDeleteFiles" => "workspace/fileOperations",
+        "notebookDocument/sync" => "notebookDocument/synchronization",
+        _ => return None,
+    };
+
+    Some(format!("/{}/dynamicRegistration", feature))
+}
+
+/// Guard for a capability registered with [`Client::register`].
+///
+/// The capability is unregistered when this guard is dropped, or explicitly with
+/// [`unregister`](Self::unregister). Use [`keep`](Self::keep) to leave it registered for the rest
+/// of the session instead.
+#[must_use = "the capability is unregistered as soon as the guard is dropped"]
+pub struct RegistrationGuard {
+    client: Client,
+    id: String,
+    method: String,
+    registered: bool,
+}
+
+impl RegistrationGuard {
+    pub(super) fn new(client: Client, id: String, method: String) -> Self {
+        RegistrationGuard {
+            client,
+            id,
+            method,
+            registered: true,
+        }
+    }
+
+    /// Returns the unique ID of this registration.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the name of the method this registration applies to.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Unregisters the capability, waiting for the client to acknowledge it.
+    ///
+    /// This corresponds to the [`client/unregisterCapability`] request.
+    ///
+    /// [`client/unregisterCapability`]: https://microsoft.github.io/language-server-protocol/specification#client_unregisterCapability
+    pub async fn unregister(mut self) -> jsonrpc::Result<()> {
+        self.registered = false;
+        let params = self.forget();
+        self.client
+            .unregister_capability(params.unregisterations)
+            .await
+    }
+
+    /// Leaves the capability registered for the rest of the session.
+    pub fn keep(mut self) {
+        self.registered = false;
+    }
+
+    fn forget(&self) -> UnregistrationParams {
+        self.client.inner.registrations.remove(&self.id);
+        UnregistrationParams {
+            unregisterations: vec![Unregistration {
+                id: self.id.clone(),
+                method: self.method.clone(),
+            }],
+        }
+    }
+}
+
+impl Debug for RegistrationGuard {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("RegistrationGuard")
+            .field("id", &self.id)
+            .field("method", &self.method)
+            .field("registered", &self.registered)
+            .finish()
+    }
+}
+
+impl Drop for RegistrationGuard {
+    fn drop(&mut self) {
+        if self.registered {
+            let params = self.forget();
+            self.client
+                .send_request_nowait::<UnregisterCapability>(params);
+        }
+    }
+}
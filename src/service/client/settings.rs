@@ -0,0 +1,441 @@
+//! Types for caching typed configuration settings of the language client.
+
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use futures::future::{BoxFuture, FutureExt};
+use lsp_types::notification::{DidChangeConfiguration, Notification};
+use lsp_types::{ConfigurationItem, DidChangeConfigurationParams};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use super::Client;
+
+/// Cache of a typed section of the client configuration.
+///
+/// The settings are pulled from the client with the [`workspace/configuration`] request if the
+/// client supports it. Otherwise, they are taken from the settings pushed by the client in
+/// [`workspace/didChangeConfiguration`] notifications, where the section is looked up as a
+/// `.`-separated path into the pushed settings object.
+///
+/// The cache is kept up to date by the service: the settings are fetched once the client sends
+/// the `initialized` notification and again on every `workspace/didChangeConfiguration`
+/// notification, before the [`LanguageServer`] handlers of these notifications are called. Use
+/// [`Settings::on_change`] to be notified of the new settings.
+///
+/// Until settings are first received, and whenever the client returns `null` for the section,
+/// the cached value is `T::default()`. Settings which fail to deserialize are logged and ignored,
+/// keeping the previous value.
+///
+/// This type is cheap to clone and all clones share the same cache.
+///
+/// [`workspace/configuration`]: https://microsoft.github.io/language-server-protocol/specification#workspace_configuration
+/// [`workspace/didChangeConfiguration`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didChangeConfiguration
+/// [`LanguageServer`]: crate::LanguageServer
+///
+/// # Example
+///
+/// ```rust
+/// # use serde::Deserialize;
+/// # use tower_lsp::jsonrpc::Result;
+/// # use tower_lsp::lsp_types::*;
+/// # use tower_lsp::{Client, LanguageServer, LspService, Settings};
+/// #
+/// #[derive(Debug, Default, Deserialize, PartialEq)]
+/// #[serde(default, rename_all = "camelCase")]
+/// struct Config {
+///     max_problems: u32,
+/// }
+///
+/// struct Backend {
+///     client: Client,
+///     settings: Settings<Config>,
+/// }
+///
+/// #[tower_lsp::async_trait]
+/// impl LanguageServer for Backend {
+///     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+///         Ok(InitializeResult::default())
+///     }
+///
+///     async fn shutdown(&self) -> Result<()> {
+///         Ok(())
+///     }
+///
+///     async fn did_save(&self, params: DidSaveTextDocumentParams) {
+///         let max_problems = self.settings.get().max_problems;
+///         // ...
+///     }
+/// }
+///
+/// let (service, socket) = LspService::new(|client| {
+///     let settings = Settings::new(client.clone(), "exampleServer");
+///     let logger = client.clone();
+///     settings.on_change(move |config: std::sync::Arc<Config>| {
+///         let logger = logger.clone();
+///         async move {
+///             let message = format!("reporting up to {} problems", config.max_problems);
+///             logger.log_message(MessageType::INFO, message).await;
+///         }
+///     });
+///
+///     Backend { client, settings }
+/// });
+/// ```
+pub struct Settings<T> {
+    inner: Arc<Inner<T>>,
+}
+
+type Callback<T> = Arc<dyn Fn(Arc<T>) -> BoxFuture<'static, ()> + Send + Sync>;
+
+struct Inner<T> {
+    client: Client,
+    section: String,
+    value: Mutex<Arc<T>>,
+    on_change: Mutex<Option<Callback<T>>>,
+}
+
+impl<T> Settings<T>
+where
+    T: DeserializeOwned + Default + PartialEq + Send + Sync + 'static,
+{
+    /// Creates a new cache for the given configuration section, initially holding
+    /// `T::default()`.
+    ///
+    /// The cache is kept up to date by the service of `client` for as long as any of its clones
+    /// are alive.
+    pub fn new(client: Client, section: impl Into<String>) -> Self {
+        let inner = Arc::new(Inner {
+            client,
+            section: section.into(),
+            value: Mutex::new(Arc::new(T::default())),
+            on_change: Mutex::new(None),
+        });
+
+        let weak = Arc::downgrade(&inner);
+        inner.client.register_settings(weak);
+        Settings { inner }
+    }
+
+    /// Returns the name of the cached configuration section.
+    pub fn section(&self) -> &str {
+        &self.inner.section
+    }
+
+    /// Returns a snapshot of the current settings.
+    pub fn get(&self) -> Arc<T> {
+        self.inner.value.lock().unwrap().clone()
+    }
+
+    /// Sets the callback awaited with the new settings whenever they change.
+    ///
+    /// When the change is triggered by a notification from the client, the callback completes
+    /// before the notification is passed on to the [`LanguageServer`]. Setting a callback replaces
+    /// the previous one.
+    ///
+    /// [`LanguageServer`]: crate::LanguageServer
+    pub fn on_change<F, Fut>(&self, callback: F)
+    where
+        F: Fn(Arc<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let callback: Callback<T> = Arc::new(move |value| callback(value).boxed());
+        *self.inner.on_change.lock().unwrap() = Some(callback);
+    }
+
+    /// Fetches the initial settings from the client.
+    ///
+    /// If the client supports dynamic registration of `workspace/didChangeConfiguration`, this
+    /// also registers for notifications about changes to the section, since some clients do not
+    /// send them otherwise.
+    ///
+    /// Returns the new settings if they differ from the cached ones.
+    pub(crate) async fn initialized(&self) -> Option<Arc<T>> {
+        let options = json!({ "section": self.inner.section });
+        match self
+            .inner
+            .client
+            .register(DidChangeConfiguration::METHOD, options)
+            .await
+        {
+            Ok(guard) => guard.keep(),
+            Err(err) => debug!("not registering for configuration changes: {}", err),
+        }
+
+        self.refresh().await
+    }
+
+    /// Updates the settings after the client signaled a configuration change.
+    ///
+    /// The settings are fetched again from the client if it supports `workspace/configuration`,
+    /// or else taken from the pushed `params`.
+    ///
+    /// Returns the new settings if they differ from the cached ones.
+    pub(crate) async fn did_change_configuration(
+        &self,
+        params: DidChangeConfigurationParams,
+    ) -> Option<Arc<T>> {
+        if self.supports_pull() {
+            return self.refresh().await;
+        }
+
+        let pushed = self
+            .inner
+            .section
+            .split('.')
+            .try_fold(&params.settings, |value, key| value.get(key));
+
+        match pushed {
+            Some(value) => self.update(value.clone()).await,
+            None => {
+                debug!("section `{}` missing from settings", self.inner.section);
+                None
+            }
+        }
+    }
+
+    /// Fetches the settings from the client, if it supports `workspace/configuration`.
+    ///
+    /// This is done automatically when the client signals a configuration change, but may be
+    /// useful when the settings depend on other state, such as the open workspace folders.
+    ///
+    /// Returns the new settings if they differ from the cached ones, after awaiting the callback
+    /// set with [`Settings::on_change`].
+    pub async fn refresh(&self) -> Option<Arc<T>> {
+        if !self.supports_pull() {
+            return None;
+        }
+
+        let item = ConfigurationItem {
+            scope_uri: None,
+            section: Some(self.inner.section.clone()),
+        };
+
+        match self.inner.client.configuration(vec![item]).await {
+            Ok(mut values) if values.len() == 1 => self.update(values.remove(0)).await,
+            Ok(values) => {
+                warn!("expected 1 configuration value, got {}", values.len());
+                None
+            }
+            Err(err) => {
+                warn!("failed to fetch section `{}`: {}", self.inner.section, err);
+                None
+            }
+        }
+    }
+
+    /// Returns `true` if the client supports the `workspace/configuration` request.
+    fn supports_pull(&self) -> bool {
+        let capabilities = self.inner.client.capabilities();
+        let workspace = capabilities.as_ref().and_then(|c| c.workspace.as_ref());
+        workspace.and_then(|w| w.configuration) == Some(true)
+    }
+
+    /// Replaces the cached settings with `value`, returning them if they changed.
+    ///
+    /// The change callback is awaited before returning.
+    async fn update(&self, value: Value) -> Option<Arc<T>> {
+        let new = match value {
+            Value::Null => T::default(),
+            value => match serde_json::from_value(value) {
+                Ok(new) => new,
+                Err(err) => {
+                    warn!("invalid settings for `{}`: {}", self.inner.section, err);
+                    return None;
+                }
+            },
+        };
+
+        let new = {
+            let mut value = self.inner.value.lock().unwrap();
+            if **value == new {
+                return None;
+            }
+
+            *value = Arc::new(new);
+            value.clone()
+        };
+
+        let callback = self.inner.on_change.lock().unwrap().clone();
+        if let Some(callback) = callback {
+            callback(new.clone()).await;
+        }
+
+        Some(new)
+    }
+}
+
+/// Type-erased [`Settings`], as kept up to date by the service.
+pub(crate) trait Section: Send + Sync {
+    /// Fetches the initial settings, see [`Settings::initialized`].
+    fn initialized(self: Arc<Self>) -> BoxFuture<'static, ()>;
+
+    /// Updates the settings, see [`Settings::did_change_configuration`].
+    fn did_change_configuration(
+        self: Arc<Self>,
+        params: DidChangeConfigurationParams,
+    ) -> BoxFuture<'static, ()>;
+}
+
+impl<T> Section for Inner<T>
+where
+    T: DeserializeOwned + Default + PartialEq + Send + Sync + 'static,
+{
+    fn initialized(self: Arc<Self>) -> BoxFuture<'static, ()> {
+        let settings = Settings { inner: self };
+        async move {
+            settings.initialized().await;
+        }
+        .boxed()
+    }
+
+    fn did_change_configuration(
+        self: Arc<Self>,
+        params: DidChangeConfigurationParams,
+    ) -> BoxFuture<'static, ()> {
+        let settings = Settings { inner: self };
+        async move {
+            settings.did_change_configuration(params).await;
+        }
+        .boxed()
+    }
+}
+
+impl<T> Clone for Settings<T> {
+    fn clone(&self) -> Self {
+        Settings {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Debug> Debug for Settings<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Settings")
+            .field("section", &self.inner.section)
+            .field("value", &self.inner.value.lock().unwrap())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+    use lsp_types::InitializeParams;
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::jsonrpc::Response;
+    use crate::service::{ServerState, State};
+
+    #[derive(Debug, Default, Deserialize, PartialEq)]
+    #[serde(default)]
+    struct Config {
+        enabled: bool,
+        level: u32,
+    }
+
+    fn client(capabilities: Value) -> (Client, crate::ClientSocket) {
+        let state = Arc::new(ServerState::new());
+        state.set(State::Initialized);
+        let (client, socket) = Client::new(state);
+
+        let params = json!({ "capabilities": capabilities });
+        let params: InitializeParams = serde_json::from_value(params).unwrap();
+        client.set_initialize_params(params);
+
+        (client, socket)
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn pulls_settings() {
+        let (client, socket) = client(json!({
+            "workspace": {
+                "configuration": true,
+                "didChangeConfiguration": {"dynamicRegistration": true},
+            },
+        }));
+        let (mut requests, mut responses) = socket.split();
+
+        let server = async move {
+            let settings = Settings::<Config>::new(client, "foo.bar");
+            let config = settings.initialized().await.unwrap();
+            assert_eq!(
+                *config,
+                Config {
+                    enabled: true,
+                    level: 1
+                }
+            );
+
+            let params = DidChangeConfigurationParams {
+                settings: json!(null),
+            };
+            assert_eq!(settings.did_change_configuration(params).await, None);
+
+            let params = DidChangeConfigurationParams {
+                settings: json!(null),
+            };
+            let config = settings.did_change_configuration(params).await.unwrap();
+            assert_eq!(*config, Config::default());
+            assert_eq!(settings.get(), config);
+        };
+
+        let client = async move {
+            let mut methods = Vec::new();
+            for result in [
+                json!(null),
+                json!([{"enabled": true, "level": 1}]),
+                json!([{"enabled": true, "level": 1}]),
+                json!([null]),
+            ] {
+                let request = requests.next().await.unwrap();
+                methods.push(request.method().to_owned());
+                let id = request.id().cloned().unwrap();
+                responses.send(Response::from_ok(id, result)).await.unwrap();
+            }
+            methods
+        };
+
+        let ((), methods) = futures::join!(server, client);
+        assert_eq!(
+            methods,
+            [
+                "client/registerCapability",
+                "workspace/configuration",
+                "workspace/configuration",
+                "workspace/configuration",
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn falls_back_to_pushed_settings() {
+        let (client, socket) = client(json!({}));
+        let settings = Settings::<Config>::new(client, "foo.bar");
+        assert_eq!(settings.initialized().await, None);
+
+        let params = DidChangeConfigurationParams {
+            settings: json!({"foo": {"bar": {"level": 2}}}),
+        };
+        let config = settings.did_change_configuration(params).await.unwrap();
+        assert_eq!(
+            *config,
+            Config {
+                enabled: false,
+                level: 2
+            }
+        );
+
+        let params = DidChangeConfigurationParams {
+            settings: json!({"foo": {"bar": {"level": "high"}}}),
+        };
+        assert_eq!(settings.did_change_configuration(params).await, None);
+        assert_eq!(settings.get().level, 2);
+
+        drop(settings);
+        assert_eq!(socket.count().await, 0);
+    }
+}
@@ -0,0 +1,124 @@
+//! Types for cancelling stale read-only requests when a text document is modified.
+
+use std::collections::HashMap;
+
+use super::pending::Pending;
+use crate::jsonrpc::Request;
+
+/// Describes how a method interacts with the text document referenced by its parameters.
+///
+/// See [`LspServiceBuilder::cancel_stale_requests`](crate::LspServiceBuilder::cancel_stale_requests)
+/// for details.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MethodKind {
+    /// The method modifies the document, e.g. `textDocument/didChange`.
+    ///
+    /// Receiving such a message cancels all pending read-only requests for the same document.
+    Mutating,
+    /// The method only reads the document, e.g. `textDocument/hover`.
+    ReadOnly,
+}
+
+impl MethodKind {
+    /// Returns the default kind of the given method, if any.
+    ///
+    /// The `didOpen`, `didChange` and `didClose` notifications of both text and notebook documents
+    /// are mutating, while all other `textDocument/*` requests are read-only.
+    fn of(method: &str, is_request: bool) -> Option<Self> {
+        match method {
+            "textDocument/didOpen"
+            | "textDocument/didChange"
+            | "textDocument/didClose"
+            | "notebookDocument/didOpen"
+            | "notebookDocument/didChange"
+            | "notebookDocument/didClose" => Some(MethodKind::Mutating),
+            _ if is_request && method.starts_with("textDocument/") => Some(MethodKind::ReadOnly),
+            _ => None,
+        }
+    }
+}
+
+/// Cancels pending read-only requests once a mutating message for the same document arrives.
+#[derive(Debug)]
+pub struct Invalidation {
+    overrides: HashMap<&'static str, Option<MethodKind>>,
+}
+
+impl Invalidation {
+    /// Creates a new `Invalidation` with the given method kinds taking precedence over the
+    /// defaults.
+    pub fn new(overrides: HashMap<&'static str, Option<MethodKind>>) -> Self {
+        Invalidation { overrides }
+    }
+
+    /// Classifies the incoming message, returning its kind and the URI of the affected document.
+    ///
+    /// Any pending read-only requests for the document are invalidated right away if the message
+    /// is mutating, so this must be called _before_ the message is passed to the inner service.
+    pub fn classify(&self, req: &Request, pending: &Pending) -> Option<(MethodKind, String)> {
+        let kind = match self.overrides.get(req.method()) {
+            Some(kind) => *kind,
+            None => MethodKind::of(req.method(), req.id().is_some()),
+        }?;
+
+        let uri = document_uri(req)?;
+        if kind == MethodKind::Mutating {
+            pending.invalidate(&uri);
+        }
+
+        Some((kind, uri))
+    }
+}
+
+/// Returns the URI of the text or notebook document referenced by the message, if any.
+fn document_uri(req: &Request) -> Option<String> {
+    let params = req.params()?;
+    let document = params
+        .get("textDocument")
+        .or_else(|| params.get("notebookDocument"))?;
+
+    document.get("uri")?.as_str().map(ToOwned::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn classifies_methods() {
+        let invalidation = Invalidation::new(HashMap::from([
+            ("custom/edit", Some(MethodKind::Mutating)),
+            ("textDocument/formatting", None),
+        ]));
+
+        let pending = Pending::new();
+        let params = json!({"textDocument":{"uri":"file:///foo.rs"}});
+        let classify = |method, id: Option<i64>| {
+            let req = Request::build(method).params(params.clone());
+            let req = match id {
+                Some(id) => req.id(id).finish(),
+                None => req.finish(),
+            };
+
+            invalidation.classify(&req, &pending).map(|(kind, _)| kind)
+        };
+
+        let uri = "file:///foo.rs".to_owned();
+        let req = Request::build("textDocument/didChange")
+            .params(params.clone())
+            .finish();
+        let classified = invalidation.classify(&req, &pending);
+        assert_eq!(classified, Some((MethodKind::Mutating, uri)));
+
+        assert_eq!(
+            classify("textDocument/hover", Some(1)),
+            Some(MethodKind::ReadOnly)
+        );
+        assert_eq!(classify("textDocument/didSave", None), None);
+        assert_eq!(classify("textDocument/formatting", Some(1)), None);
+        assert_eq!(classify("custom/edit", None), Some(MethodKind::Mutating));
+        assert_eq!(classify("workspace/symbol", Some(1)), None);
+    }
+}
@@ -1,15 +1,26 @@
 //! Assorted middleware that implements LSP server semantics.
 
+use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use futures::future::{self, BoxFuture, FutureExt};
-use tower::{Layer, Service};
+use futures::lock::Mutex;
+use lsp_types::{
+    ClientCapabilities, DidChangeConfigurationParams, DidChangeWorkspaceFoldersParams,
+    InitializeParams, ServerCapabilities,
+};
+use serde_json::Value;
+use tower::layer::util::Stack;
+use tower::util::{BoxLayer, BoxService};
+use tower::{layer::layer_fn, BoxError, Layer, Service, ServiceExt};
 use tracing::{info, warn};
 
 use super::ExitedError;
-use crate::jsonrpc::{not_initialized_error, Error, Id, Request, Response};
+use crate::capabilities::{self, Mismatch};
+use crate::jsonrpc::{not_initialized_error, Error, ErrorCode, Id, Request, Response};
+use crate::position::PositionEncoding;
 
 use super::client::Client;
 use super::pending::Pending;
@@ -23,11 +34,23 @@ use super::state::{ServerState, State};
 pub struct Initialize {
     state: Arc<ServerState>,
     pending: Arc<Pending>,
+    client: Client,
+    implemented: Option<&'static [&'static str]>,
 }
 
 impl Initialize {
-    pub fn new(state: Arc<ServerState>, pending: Arc<Pending>) -> Self {
-        Initialize { state, pending }
+    pub fn new(
+        state: Arc<ServerState>,
+        pending: Arc<Pending>,
+        client: Client,
+        implemented: Option<&'static [&'static str]>,
+    ) -> Self {
+        Initialize {
+            state,
+            pending,
+            client,
+            implemented,
+        }
     }
 }
 
@@ -38,6 +61,8 @@ impl<S> Layer<S> for Initialize {
         InitializeService {
             inner: Cancellable::new(inner, self.pending.clone()),
             state: self.state.clone(),
+            client: self.client.clone(),
+            implemented: self.implemented,
         }
     }
 }
@@ -46,6 +71,8 @@ impl<S> Layer<S> for Initialize {
 pub struct InitializeService<S> {
     inner: Cancellable<S>,
     state: Arc<ServerState>,
+    client: Client,
+    implemented: Option<&'static [&'static str]>,
 }
 
 impl<S> Service<Request> for InitializeService<S>
@@ -63,14 +90,41 @@ where
 
     fn call(&mut self, req: Request) -> Self::Future {
         if self.state.get() == State::Uninitialized {
+            let trace = req.params().and_then(|p| p.get("trace")).cloned();
+            match trace.map(serde_json::from_value) {
+                Some(Ok(value)) => self.client.set_trace(value),
+                Some(Err(err)) => warn!("received invalid `trace` value, ignoring: {}", err),
+                None => {}
+            }
+
+            let params = req
+                .params()
+                .cloned()
+                .map(serde_json::from_value::<InitializeParams>);
+
             let state = self.state.clone();
+            let client = self.client.clone();
+            let implemented = self.implemented;
             let fut = self.inner.call(req);
 
             Box::pin(async move {
                 let response = fut.await?;
 
                 match &response {
-                    Some(res) if res.is_ok() => state.set(State::Initialized),
+                    Some(res) if res.is_ok() => {
+                        let params = params.and_then(Result::ok);
+                        let offered = params.as_ref().map(|p| &p.capabilities);
+                        client.set_position_encoding(position_encoding(res, offered));
+                        if let Some(params) = params {
+                            client.set_initialize_params(params);
+                        }
+
+                        if let Some(implemented) = implemented {
+                            check_capabilities(res, implemented);
+                        }
+
+                        state.set(State::Initialized);
+                    }
                     _ => state.set(State::Uninitialized),
                 }
 
@@ -84,6 +138,63 @@ where
     }
 }
 
+/// Returns the position encoding selected by the server in its `initialize` response.
+///
+/// Falls back to UTF-16 if the server did not select an encoding or selected an unknown one.
+fn position_encoding(res: &Response, client: Option<&ClientCapabilities>) -> PositionEncoding {
+    let kind = res
+        .result()
+        .and_then(|r| r.pointer("/capabilities/positionEncoding"))
+        .and_then(Value::as_str);
+
+    let kind = match kind {
+        Some(kind) => kind,
+        None => return PositionEncoding::Utf16,
+    };
+
+    let offered = client
+        .and_then(|c| c.general.as_ref())
+        .and_then(|g| g.position_encodings.as_deref())
+        .unwrap_or_default();
+
+    if kind != "utf-16" && !offered.iter().any(|k| k.as_str() == kind) {
+        warn!(
+            "server selected position encoding `{}` not offered by client",
+            kind
+        );
+    }
+
+    PositionEncoding::from_kind(&kind.to_owned().into()).unwrap_or_else(|| {
+        warn!(
+            "server selected unknown position encoding `{}`, assuming UTF-16",
+            kind
+        );
+        PositionEncoding::Utf16
+    })
+}
+
+/// Logs any mismatches between the capabilities in the `initialize` response and the methods
+/// implemented by the server.
+fn check_capabilities(res: &Response, implemented: &[&str]) {
+    let capabilities = res
+        .result()
+        .and_then(|r| r.get("capabilities"))
+        .cloned()
+        .map(serde_json::from_value::<ServerCapabilities>);
+
+    let capabilities = match capabilities {
+        Some(Ok(capabilities)) => capabilities,
+        _ => return,
+    };
+
+    for mismatch in capabilities::check(&capabilities, implemented) {
+        match mismatch {
+            Mismatch::Unimplemented { .. } => warn!("server capability mismatch: {}", mismatch),
+            Mismatch::Unadvertised { .. } => info!("server capability mismatch: {}", mismatch),
+        }
+    }
+}
+
 /// Middleware which implements `shutdown` request semantics.
 ///
 /// # Specification
@@ -262,6 +373,342 @@ where
     }
 }
 
+/// Middleware which keeps track of workspace folder changes, in addition to the semantics of
+/// [`Normal`].
+///
+/// # Specification
+///
+/// https://microsoft.github.io/language-server-protocol/specification#workspace_didChangeWorkspaceFolders
+pub struct DidChangeWorkspaceFolders {
+    normal: Normal,
+    client: Client,
+}
+
+impl DidChangeWorkspaceFolders {
+    pub fn new(state: Arc<ServerState>, pending: Arc<Pending>, client: Client) -> Self {
+        DidChangeWorkspaceFolders {
+            normal: Normal::new(state, pending),
+            client,
+        }
+    }
+}
+
+impl<S> Layer<S> for DidChangeWorkspaceFolders {
+    type Service = DidChangeWorkspaceFoldersService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DidChangeWorkspaceFoldersService {
+            inner: self.normal.layer(inner),
+            state: self.normal.state.clone(),
+            client: self.client.clone(),
+        }
+    }
+}
+
+/// Service created from [`DidChangeWorkspaceFolders`] layer.
+pub struct DidChangeWorkspaceFoldersService<S> {
+    inner: NormalService<S>,
+    state: Arc<ServerState>,
+    client: Client,
+}
+
+impl<S> Service<Request> for DidChangeWorkspaceFoldersService<S>
+where
+    S: Service<Request, Response = Option<Response>, Error = ExitedError>,
+    S::Future: Into<BoxFuture<'static, Result<Option<Response>, S::Error>>> + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if self.state.get() == State::Initialized {
+            let params = req.params().cloned().map(serde_json::from_value);
+            match params {
+                Some(Ok(DidChangeWorkspaceFoldersParams { event })) => {
+                    self.client.update_workspace_folders(event)
+                }
+                Some(Err(err)) => warn!("received invalid workspace folders, ignoring: {}", err),
+                None => {}
+            }
+        }
+
+        self.inner.call(req)
+    }
+}
+
+/// Middleware which keeps the [`Settings`] of the client up to date, in addition to the semantics
+/// of [`Normal`].
+///
+/// Settings are fetched on the `initialized` notification and updated on the
+/// `workspace/didChangeConfiguration` notification, before the notification is handled.
+///
+/// [`Settings`]: crate::Settings
+///
+/// # Specification
+///
+/// https://microsoft.github.io/language-server-protocol/specification#workspace_didChangeConfiguration
+pub struct Configuration {
+    normal: Normal,
+    client: Client,
+}
+
+impl Configuration {
+    pub fn new(state: Arc<ServerState>, pending: Arc<Pending>, client: Client) -> Self {
+        Configuration {
+            normal: Normal::new(state, pending),
+            client,
+        }
+    }
+}
+
+impl<S> Layer<S> for Configuration {
+    type Service = ConfigurationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConfigurationService {
+            inner: self.normal.layer(inner),
+            state: self.normal.state.clone(),
+            client: self.client.clone(),
+        }
+    }
+}
+
+/// Service created from [`Configuration`] layer.
+pub struct ConfigurationService<S> {
+    inner: NormalService<S>,
+    state: Arc<ServerState>,
+    client: Client,
+}
+
+impl<S> Service<Request> for ConfigurationService<S>
+where
+    S: Service<Request, Response = Option<Response>, Error = ExitedError>,
+    S::Future: Into<BoxFuture<'static, Result<Option<Response>, S::Error>>> + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let mut updates = Vec::new();
+        if self.state.get() == State::Initialized {
+            let sections = self.client.settings();
+            if req.method() == "initialized" {
+                updates.extend(sections.into_iter().map(|section| section.initialized()));
+            } else {
+                let params = req.params().cloned().map(serde_json::from_value);
+                match params {
+                    Some(Ok(DidChangeConfigurationParams { settings })) => {
+                        for section in sections {
+                            let params = DidChangeConfigurationParams {
+                                settings: settings.clone(),
+                            };
+                            updates.push(section.did_change_configuration(params));
+                        }
+                    }
+                    Some(Err(err)) => warn!("received invalid configuration, ignoring: {}", err),
+                    None => {}
+                }
+            }
+        }
+
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            future::join_all(updates).await;
+            fut.await
+        })
+    }
+}
+
+/// Type-erased method handler, as seen by user-provided middleware.
+pub type MethodService = BoxService<Request, Option<Response>, BoxError>;
+
+type BoxMethodLayer = BoxLayer<MethodService, Request, Option<Response>, BoxError>;
+
+/// User-provided layers which apply either to all method handlers or to specific ones only.
+#[derive(Clone)]
+pub struct Middleware {
+    global: Option<BoxMethodLayer>,
+    methods: HashMap<&'static str, BoxMethodLayer>,
+}
+
+impl Middleware {
+    pub fn new() -> Self {
+        Middleware {
+            global: None,
+            methods: HashMap::new(),
+        }
+    }
+
+    /// Adds the given layer beneath all previously added global layers.
+    pub fn push<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<MethodService> + Send + Sync + 'static,
+        L::Service: Service<Request, Response = Option<Response>> + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<BoxError>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        let layer = match self.global.take() {
+            Some(outer) => BoxLayer::new(Stack::new(box_layer(layer), outer)),
+            None => box_layer(layer),
+        };
+
+        self.global = Some(layer);
+        self
+    }
+
+    /// Adds the given layer beneath all previously added layers of the method `name`.
+    pub fn push_method<L>(mut self, name: &'static str, layer: L) -> Self
+    where
+        L: Layer<MethodService> + Send + Sync + 'static,
+        L::Service: Service<Request, Response = Option<Response>> + Send + 'static,
+        <L::Service as Service<Request>>::Error: Into<BoxError>,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        let layer = match self.methods.remove(name) {
+            Some(outer) => BoxLayer::new(Stack::new(box_layer(layer), outer)),
+            None => box_layer(layer),
+        };
+
+        self.methods.insert(name, layer);
+        self
+    }
+
+    /// Returns the layer wrapping the handler of the method `name`.
+    ///
+    /// Method-specific layers are placed beneath the global layers.
+    pub fn for_method(&self, name: &str) -> MethodMiddleware {
+        let layer = match (self.methods.get(name), &self.global) {
+            (Some(inner), Some(outer)) => {
+                Some(BoxLayer::new(Stack::new(inner.clone(), outer.clone())))
+            }
+            (Some(inner), None) => Some(inner.clone()),
+            (None, outer) => outer.clone(),
+        };
+
+        MethodMiddleware(layer)
+    }
+}
+
+fn box_layer<L>(layer: L) -> BoxMethodLayer
+where
+    L: Layer<MethodService> + Send + Sync + 'static,
+    L::Service: Service<Request, Response = Option<Response>> + Send + 'static,
+    <L::Service as Service<Request>>::Error: Into<BoxError>,
+    <L::Service as Service<Request>>::Future: Send + 'static,
+{
+    BoxLayer::new(layer_fn(move |service| {
+        BoxService::new(layer.layer(service).map_err(Into::into))
+    }))
+}
+
+/// Middleware which applies user-provided layers around a single method handler.
+///
+/// This layer is meant to be placed beneath the lifecycle layers above, so the user-provided
+/// layers only ever observe requests which the server is ready to handle.
+pub struct MethodMiddleware(Option<BoxMethodLayer>);
+
+impl<S> Layer<S> for MethodMiddleware
+where
+    S: Service<Request, Response = Option<Response>, Error = ExitedError> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Service = MiddlewareService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let inner = match &self.0 {
+            Some(layer) => {
+                let inner = BoxService::new(inner.map_err(BoxError::from));
+                Inner::Layered(Arc::new(Mutex::new(layer.layer(inner))))
+            }
+            None => Inner::Direct(inner),
+        };
+
+        MiddlewareService { inner }
+    }
+}
+
+/// Service created from [`MethodMiddleware`] layer.
+///
+/// Requests are passed straight to the inner service when no user-provided layers apply.
+pub struct MiddlewareService<S> {
+    inner: Inner<S>,
+}
+
+enum Inner<S> {
+    Direct(S),
+    Layered(Arc<Mutex<MethodService>>),
+}
+
+impl<S> Service<Request> for MiddlewareService<S>
+where
+    S: Service<Request, Response = Option<Response>, Error = ExitedError>,
+    S::Future: Into<BoxFuture<'static, Result<Option<Response>, S::Error>>>,
+{
+    type Response = Option<Response>;
+    type Error = ExitedError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.inner {
+            Inner::Direct(inner) => inner.poll_ready(cx),
+            Inner::Layered(_) => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let inner = match &mut self.inner {
+            Inner::Direct(inner) => return inner.call(req).into(),
+            Inner::Layered(inner) => inner.clone(),
+        };
+
+        Box::pin(async move {
+            let id = req.id().cloned();
+
+            // Readiness is driven here rather than in `poll_ready`, since the router calls method
+            // handlers directly. Only the response future is awaited outside of the lock.
+            let fut = {
+                let mut inner = inner.lock().await;
+                match ServiceExt::<Request>::ready(&mut *inner).await {
+                    Ok(service) => service.call(req),
+                    Err(err) => return middleware_error(id, err),
+                }
+            };
+
+            match fut.await {
+                Ok(response) => Ok(response),
+                Err(err) => middleware_error(id, err),
+            }
+        })
+    }
+}
+
+fn middleware_error(id: Option<Id>, err: BoxError) -> Result<Option<Response>, ExitedError> {
+    if err.is::<ExitedError>() {
+        return Err(ExitedError(()));
+    }
+
+    warn!("method middleware returned an error: {}", err);
+    Ok(id.map(|id| {
+        let error = Error {
+            code: ErrorCode::InternalError,
+            message: err.to_string().into(),
+            data: None,
+        };
+
+        Response::from_error(id, error)
+    }))
+}
+
 /// Wraps an inner service `S` and implements `$/cancelRequest` semantics for all requests.
 ///
 /// # Specification
@@ -0,0 +1,105 @@
+//! Types for processing client-to-server notifications in the order they arrive.
+
+use std::collections::HashSet;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+
+use futures::future::{self, BoxFuture, FutureExt, Shared};
+
+use crate::jsonrpc::Request;
+
+/// Chains the handlers of incoming notifications so that they run one after another.
+///
+/// Only notifications with one of the given method names are chained, which allows built-in
+/// notifications like `$/cancelRequest` to bypass the queue.
+pub struct Sequencer {
+    methods: HashSet<&'static str>,
+    last: Shared<BoxFuture<'static, ()>>,
+}
+
+impl Sequencer {
+    /// Creates a new sequencer for notifications with the given method names.
+    pub fn new<I: IntoIterator<Item = &'static str>>(methods: I) -> Self {
+        Sequencer {
+            methods: methods.into_iter().collect(),
+            last: future::ready(()).boxed().shared(),
+        }
+    }
+
+    /// Returns `true` if the given message is a notification which must be processed in order.
+    pub fn is_ordered(&self, req: &Request) -> bool {
+        req.id().is_none() && self.methods.contains(req.method())
+    }
+
+    /// Returns a future which only starts resolving `fut` once every future previously passed to
+    /// this method has resolved.
+    ///
+    /// The returned futures need not be polled in order, since each of them drives its
+    /// predecessors to completion as well.
+    pub fn schedule<F>(&mut self, fut: F) -> BoxFuture<'static, F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Clone + Send + Sync,
+    {
+        let prev = self.last.clone();
+        let next = async move {
+            prev.await;
+            fut.await
+        }
+        .boxed()
+        .shared();
+
+        self.last = next.clone().map(drop).boxed().shared();
+        next.boxed()
+    }
+}
+
+impl Debug for Sequencer {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Sequencer")
+            .field("methods", &self.methods)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn runs_scheduled_futures_in_order() {
+        let mut sequencer = Sequencer::new(["foo"]);
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let (tx, rx) = futures::channel::oneshot::channel::<()>();
+        let log1 = log.clone();
+        let first = sequencer.schedule(async move {
+            rx.await.unwrap();
+            log1.lock().unwrap().push(1);
+        });
+
+        let log2 = log.clone();
+        let second = sequencer.schedule(async move { log2.lock().unwrap().push(2) });
+
+        let second = tokio::spawn(second);
+        tokio::task::yield_now().await;
+        assert!(log.lock().unwrap().is_empty());
+
+        tx.send(()).unwrap();
+        second.await.unwrap();
+        first.await;
+
+        assert_eq!(*log.lock().unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn orders_only_known_notifications() {
+        let sequencer = Sequencer::new(["foo"]);
+
+        assert!(sequencer.is_ordered(&Request::build("foo").finish()));
+        assert!(!sequencer.is_ordered(&Request::build("foo").id(1).finish()));
+        assert!(!sequencer.is_ordered(&Request::build("bar").finish()));
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::fmt::{self, Debug, Formatter};
 use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use dashmap::{mapref::entry::Entry, DashMap};
@@ -12,7 +13,21 @@ use super::ExitedError;
 use crate::jsonrpc::{Error, Id, Response};
 
 /// A hashmap containing pending server requests, keyed by request ID.
-pub struct Pending(Arc<DashMap<Id, future::AbortHandle>>);
+pub struct Pending(Arc<DashMap<Id, Handle>>);
+
+struct Handle {
+    abort: future::AbortHandle,
+    document: Option<String>,
+    content_modified: Arc<AtomicBool>,
+}
+
+impl Handle {
+    fn abort(&self, content_modified: bool) {
+        self.content_modified
+            .store(content_modified, Ordering::SeqCst);
+        self.abort.abort();
+    }
+}
 
 impl Pending {
     /// Creates a new pending server requests map.
@@ -23,7 +38,9 @@ impl Pending {
     /// Executes the given async request handler, keyed by the given request ID.
     ///
     /// If a cancel request is issued before the future is finished resolving, this will resolve to
-    /// a "canceled" error response, and the pending request handler future will be dropped.
+    /// a "canceled" error response, and the pending request handler future will be dropped. If the
+    /// request is invalidated with [`Pending::invalidate`] instead, this will resolve to a "content
+    /// modified" error response.
     pub fn execute<F>(
         &self,
         id: Id,
@@ -33,8 +50,13 @@ impl Pending {
         F: Future<Output = Result<Option<Response>, ExitedError>> + Send + 'static,
     {
         if let Entry::Vacant(entry) = self.0.entry(id.clone()) {
-            let (handler_fut, abort_handle) = future::abortable(fut);
-            entry.insert(abort_handle);
+            let (handler_fut, abort) = future::abortable(fut);
+            let content_modified = Arc::new(AtomicBool::new(false));
+            entry.insert(Handle {
+                abort,
+                document: None,
+                content_modified: content_modified.clone(),
+            });
 
             let requests = self.0.clone();
             Either::Left(async move {
@@ -43,6 +65,8 @@ impl Pending {
 
                 if let Ok(handler_result) = abort_result {
                     handler_result
+                } else if content_modified.load(Ordering::SeqCst) {
+                    Ok(Some(Response::from_error(id, Error::content_modified())))
                 } else {
                     Ok(Some(Response::from_error(id, Error::request_cancelled())))
                 }
@@ -58,7 +82,7 @@ impl Pending {
     /// already completed, this method call will do nothing.
     pub fn cancel(&self, id: &Id) {
         if let Some((_, handle)) = self.0.remove(id) {
-            handle.abort();
+            handle.abort(false);
             info!("successfully cancelled request with ID: {}", id);
         } else {
             debug!(
@@ -71,10 +95,37 @@ impl Pending {
     /// Cancels all pending request handlers, if any.
     pub fn cancel_all(&self) {
         self.0.retain(|_, handle| {
-            handle.abort();
+            handle.abort(false);
             false
         });
     }
+
+    /// Associates the running request handler corresponding to this ID with a text document.
+    ///
+    /// If no such request handler is running, this method call will do nothing.
+    pub fn set_document(&self, id: &Id, uri: String) {
+        if let Some(mut handle) = self.0.get_mut(id) {
+            handle.document = Some(uri);
+        }
+    }
+
+    /// Cancels all running request handlers associated with the given text document.
+    ///
+    /// This will force the futures to resolve to a "content modified" error response.
+    pub fn invalidate(&self, uri: &str) {
+        self.0.retain(|id, handle| {
+            if handle.document.as_deref() == Some(uri) {
+                handle.abort(true);
+                info!(
+                    "invalidated request with ID {} after {} was modified",
+                    id, uri
+                );
+                false
+            } else {
+                true
+            }
+        });
+    }
 }
 
 impl Debug for Pending {
@@ -121,4 +172,26 @@ mod tests {
             Ok(Some(Response::from_error(id, Error::request_cancelled())))
         );
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn invalidates_server_request() {
+        let pending = Pending::new();
+
+        let id = Id::Number(1);
+        let handler_fut = tokio::spawn(pending.execute(id.clone(), future::pending()));
+        let other_fut = tokio::spawn(pending.execute(Id::Number(2), future::pending()));
+        pending.set_document(&id, "file:///foo.rs".into());
+        pending.set_document(&Id::Number(2), "file:///bar.rs".into());
+
+        pending.invalidate("file:///foo.rs");
+
+        let res = handler_fut.await.expect("task panicked");
+        assert_eq!(
+            res,
+            Ok(Some(Response::from_error(id, Error::content_modified())))
+        );
+
+        assert_eq!(format!("{:?}", pending), "{Number(2)}");
+        other_fut.abort();
+    }
 }
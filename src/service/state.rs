@@ -3,6 +3,8 @@
 use std::fmt::{self, Debug, Formatter};
 use std::sync::atomic::{AtomicU8, Ordering};
 
+use lsp_types::TraceValue;
+
 /// A list of possible states the language server can be in.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(u8)]
@@ -48,3 +50,37 @@ impl Debug for ServerState {
         self.get().fmt(f)
     }
 }
+
+/// Atomic value which represents the current trace level requested by the client.
+pub struct Trace(AtomicU8);
+
+impl Trace {
+    pub const fn new() -> Self {
+        Trace(AtomicU8::new(0))
+    }
+
+    pub fn set(&self, value: TraceValue) {
+        let value = match value {
+            TraceValue::Off => 0,
+            TraceValue::Messages => 1,
+            TraceValue::Verbose => 2,
+        };
+
+        self.0.store(value, Ordering::SeqCst);
+    }
+
+    pub fn get(&self) -> TraceValue {
+        match self.0.load(Ordering::SeqCst) {
+            0 => TraceValue::Off,
+            1 => TraceValue::Messages,
+            2 => TraceValue::Verbose,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Debug for Trace {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.get().fmt(f)
+    }
+}
@@ -0,0 +1,396 @@
+//! Harness for testing language servers against an in-process fake client.
+//!
+//! A [`TestClient`] connects a service to a fake language client through in-memory channels, so
+//! that every message passes through a [`Server`] just like on a real connection, including the
+//! server-to-client traffic of the [`Client`](crate::Client). The server is driven by the futures
+//! returned from the test client, so tests run on any executor without spawning tasks.
+//!
+//! This module is only available when the `testing` crate feature is enabled.
+//!
+//! [`Server`]: crate::Server
+//!
+//! # Example
+//!
+//! ```rust
+//! use tower_lsp::jsonrpc::Result;
+//! use tower_lsp::lsp_types::notification::LogMessage;
+//! use tower_lsp::lsp_types::request::HoverRequest;
+//! use tower_lsp::lsp_types::*;
+//! use tower_lsp::testing::TestClient;
+//! use tower_lsp::{Client, LanguageServer, LspService};
+//!
+//! struct Backend {
+//!     client: Client,
+//! }
+//!
+//! #[tower_lsp::async_trait]
+//! impl LanguageServer for Backend {
+//!     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//!         Ok(InitializeResult::default())
+//!     }
+//!
+//!     async fn initialized(&self, _: InitializedParams) {
+//!         self.client.log_message(MessageType::INFO, "ready").await;
+//!     }
+//!
+//!     async fn shutdown(&self) -> Result<()> {
+//!         Ok(())
+//!     }
+//!
+//!     async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+//!         Ok(None)
+//!     }
+//! }
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let (service, socket) = LspService::new(|client| Backend { client });
+//! let mut client = TestClient::new(service, socket);
+//! client.initialize(InitializeParams::default()).await.unwrap();
+//!
+//! let log = client.next_notification::<LogMessage>().await.unwrap();
+//! assert_eq!(log.message, "ready");
+//!
+//! let uri = "file:///foo.rs".parse().unwrap();
+//! let params = HoverParams {
+//!     text_document_position_params: TextDocumentPositionParams {
+//!         text_document: TextDocumentIdentifier { uri },
+//!         position: Position::new(0, 0),
+//!     },
+//!     work_done_progress_params: Default::default(),
+//! };
+//! assert_eq!(client.request::<HoverRequest>(params).await, Ok(None));
+//!
+//! client.shutdown().await.unwrap();
+//! # }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Debug, Formatter};
+
+use lsp_types::notification::{Exit, Initialized, Notification};
+use lsp_types::request::{Initialize, Shutdown};
+use lsp_types::{InitializeParams, InitializeResult, InitializedParams};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use tower::Service;
+use tracing::warn;
+
+use crate::connection::Connection;
+use crate::jsonrpc::{self, Error, ErrorCode, Id, Message, Request, Response};
+use crate::ClientSocket;
+
+type Handler = Box<dyn FnMut(Option<Value>) -> jsonrpc::Result<Value>>;
+
+/// Fake language client connected to a service through in-memory channels.
+///
+/// Requests sent by the server to the client are answered by the handlers registered with
+/// [`on_request`](Self::on_request), or else with a "Method not found" error, so that the server
+/// never waits for a response which the test does not provide. All server-to-client requests and
+/// notifications are recorded and can be inspected with [`next_request`](Self::next_request) and
+/// [`next_notification`](Self::next_notification).
+///
+/// Note that the server only sends requests which are supported according to the client
+/// capabilities passed to [`initialize`](Self::initialize).
+///
+/// See the [module-level documentation](self) for an example.
+pub struct TestClient {
+    connection: Connection,
+    next_id: i64,
+    handlers: HashMap<String, Handler>,
+    received: VecDeque<Request>,
+}
+
+impl TestClient {
+    /// Creates a new `TestClient` connected to the given service and client socket.
+    pub fn new<T>(service: T, socket: ClientSocket) -> Self
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        TestClient {
+            connection: Connection::new(service, socket),
+            next_id: 0,
+            handlers: HashMap::new(),
+            received: VecDeque::new(),
+        }
+    }
+
+    /// Performs the `initialize` handshake, returning the result of the [`initialize`] request.
+    ///
+    /// The [`initialized`] notification is sent only if the request succeeded.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    /// [`initialized`]: https://microsoft.github.io/language-server-protocol/specification#initialized
+    pub async fn initialize(
+        &mut self,
+        params: InitializeParams,
+    ) -> jsonrpc::Result<InitializeResult> {
+        let result = self.request::<Initialize>(params).await?;
+        self.notify::<Initialized>(InitializedParams {});
+        Ok(result)
+    }
+
+    /// Sends a request to the server and waits for its response.
+    ///
+    /// While waiting, requests from the server are answered as described in [`TestClient`].
+    /// Returns `Err` with error code [`ErrorCode::InternalError`] if the server stops before
+    /// responding.
+    pub async fn request<R>(&mut self, params: R::Params) -> jsonrpc::Result<R::Result>
+    where
+        R: lsp_types::request::Request,
+    {
+        let id = Id::Number(self.next_id);
+        self.next_id += 1;
+        let req = build_request(R::METHOD, Some(id.clone()), params);
+        self.connection.send(Message::Request(req));
+
+        loop {
+            match self.connection.recv().await {
+                Some(Message::Response(res)) => {
+                    let (res_id, result) = res.into_parts();
+                    if res_id != id {
+                        warn!("received response to unknown request {}", res_id);
+                        continue;
+                    }
+
+                    return result.and_then(|v| {
+                        serde_json::from_value(v).map_err(|e| Error {
+                            code: ErrorCode::ParseError,
+                            message: e.to_string().into(),
+                            data: None,
+                        })
+                    });
+                }
+                Some(msg) => self.handle(msg),
+                None => return Err(Error::internal_error()),
+            }
+        }
+    }
+
+    /// Sends a notification to the server.
+    ///
+    /// The notification is processed once the server is driven by a later call.
+    pub fn notify<N>(&mut self, params: N::Params)
+    where
+        N: Notification,
+    {
+        let req = build_request(N::METHOD, None, params);
+        self.connection.send(Message::Request(req));
+    }
+
+    /// Answers all future server-to-client requests of type `R` with `handler`.
+    ///
+    /// This replaces any handler previously registered for the same request type.
+    pub fn on_request<R, F>(&mut self, mut handler: F)
+    where
+        R: lsp_types::request::Request,
+        F: FnMut(R::Params) -> jsonrpc::Result<R::Result> + 'static,
+    {
+        let handler = move |params: Option<Value>| {
+            let params = serde_json::from_value(params.unwrap_or(Value::Null))
+                .map_err(|e| Error::invalid_params(e.to_string()))?;
+            let result = handler(params)?;
+            Ok(serde_json::to_value(result).unwrap())
+        };
+
+        self.handlers
+            .insert(R::METHOD.to_owned(), Box::new(handler));
+    }
+
+    /// Returns the parameters of the next request of type `R` sent by the server.
+    ///
+    /// Requests already received are returned first, in order. Otherwise, this waits for the
+    /// server to send one, returning `None` if the server stops first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameters of the request could not be deserialized into `R::Params`.
+    pub async fn next_request<R>(&mut self) -> Option<R::Params>
+    where
+        R: lsp_types::request::Request,
+    {
+        self.next_received(R::METHOD, true).await
+    }
+
+    /// Returns the parameters of the next notification of type `N` sent by the server.
+    ///
+    /// Notifications already received are returned first, in order. Otherwise, this waits for the
+    /// server to send one, returning `None` if the server stops first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameters of the notification could not be deserialized into `N::Params`.
+    pub async fn next_notification<N>(&mut self) -> Option<N::Params>
+    where
+        N: Notification,
+    {
+        self.next_received(N::METHOD, false).await
+    }
+
+    /// Sends the `shutdown` request and the `exit` notification, and waits for the server to stop.
+    ///
+    /// Returns the result of the `shutdown` request.
+    pub async fn shutdown(mut self) -> jsonrpc::Result<()> {
+        let result = self.request::<Shutdown>(()).await;
+        self.notify::<Exit>(());
+        self.connection.close();
+
+        while let Some(msg) = self.connection.recv().await {
+            self.handle(msg);
+        }
+
+        result
+    }
+
+    /// Records a message sent by the server, answering it if it is a request.
+    fn handle(&mut self, msg: Message) {
+        match msg {
+            Message::Request(req) => {
+                if let Some(id) = req.id().cloned() {
+                    let params = req.params().cloned();
+                    let result = match self.handlers.get_mut(req.method()) {
+                        Some(handler) => handler(params),
+                        None => Err(Error::method_not_found()),
+                    };
+
+                    let res = Response::from_parts(id, result);
+                    self.connection.send(Message::Response(res));
+                }
+
+                self.received.push_back(req);
+            }
+            Message::Response(res) => {
+                let (id, _) = res.into_parts();
+                warn!("received response to unknown request {}", id);
+            }
+            Message::Batch(batch) => batch.into_iter().for_each(|msg| self.handle(msg)),
+        }
+    }
+
+    async fn next_received<P>(&mut self, method: &str, is_request: bool) -> Option<P>
+    where
+        P: DeserializeOwned,
+    {
+        loop {
+            let pos = self
+                .received
+                .iter()
+                .position(|req| req.method() == method && req.id().is_some() == is_request);
+
+            if let Some(req) = pos.and_then(|i| self.received.remove(i)) {
+                let (_, _, params) = req.into_parts();
+                let params = serde_json::from_value(params.unwrap_or(Value::Null));
+                return Some(params.expect("invalid parameters sent by server"));
+            }
+
+            let msg = self.connection.recv().await?;
+            self.handle(msg);
+        }
+    }
+}
+
+/// Builds a request, omitting its parameters if they serialize to `null`, as for `shutdown`.
+fn build_request<P: Serialize>(method: &'static str, id: Option<Id>, params: P) -> Request {
+    let mut builder = Request::build(method);
+    if let Some(id) = id {
+        builder = builder.id(id);
+    }
+
+    match serde_json::to_value(params).unwrap() {
+        Value::Null => builder.finish(),
+        params => builder.params(params).finish(),
+    }
+}
+
+impl Debug for TestClient {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("TestClient")
+            .field("next_id", &self.next_id)
+            .field("handlers", &self.handlers.keys())
+            .field("received", &self.received)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::notification::LogMessage;
+    use lsp_types::request::WorkspaceConfiguration;
+    use lsp_types::*;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{Client, LanguageServer, LspService};
+
+    #[derive(Debug)]
+    struct Mock {
+        client: Client,
+    }
+
+    #[async_trait::async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: InitializeParams) -> jsonrpc::Result<InitializeResult> {
+            Ok(InitializeResult::default())
+        }
+
+        async fn initialized(&self, _: InitializedParams) {
+            let item = ConfigurationItem {
+                scope_uri: None,
+                section: Some("mock".into()),
+            };
+
+            let message = match self.client.configuration(vec![item]).await {
+                Ok(values) => values[0].to_string(),
+                Err(err) => err.to_string(),
+            };
+
+            self.client.log_message(MessageType::INFO, message).await;
+        }
+
+        async fn shutdown(&self) -> jsonrpc::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn params(configuration: bool) -> InitializeParams {
+        let capabilities = json!({ "workspace": { "configuration": configuration } });
+        let params = json!({ "capabilities": capabilities });
+        serde_json::from_value(params).unwrap()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn answers_server_requests() {
+        let (service, socket) = LspService::new(|client| Mock { client });
+        let mut client = TestClient::new(service, socket);
+        client.on_request::<WorkspaceConfiguration, _>(|params| {
+            assert_eq!(params.items[0].section.as_deref(), Some("mock"));
+            Ok(vec![json!({"enabled": true})])
+        });
+
+        let result = client.initialize(params(true)).await.unwrap();
+        assert_eq!(result, InitializeResult::default());
+
+        let log = client.next_notification::<LogMessage>().await.unwrap();
+        assert_eq!(log.message, r#"{"enabled":true}"#);
+
+        let request = client.next_request::<WorkspaceConfiguration>().await;
+        assert_eq!(request.unwrap().items.len(), 1);
+
+        assert_eq!(client.shutdown().await, Ok(()));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn rejects_unhandled_server_requests() {
+        let (service, socket) = LspService::new(|client| Mock { client });
+        let mut client = TestClient::new(service, socket);
+        client.initialize(params(true)).await.unwrap();
+
+        let log = client.next_notification::<LogMessage>().await.unwrap();
+        assert_eq!(log.message, "Method not found: Method not found");
+
+        assert_eq!(client.shutdown().await, Ok(()));
+    }
+}
@@ -0,0 +1,484 @@
+//! Recording and replaying of language server sessions.
+//!
+//! A [`Server`] records every message it exchanges with the client when configured with
+//! [`Server::record_trace`]. Each line of the trace is a [`TraceEntry`] in JSON format, such as:
+//!
+//! ```json
+//! {"timestamp":1697500000000,"direction":"incoming","message":{"jsonrpc":"2.0","method":"shutdown","id":1}}
+//! ```
+//!
+//! Traces sent along with bug reports can then be fed back into a service with [`Replay`], which
+//! reports every response of the service that differs from the recorded one.
+//!
+//! [`Server`]: crate::Server
+//! [`Server::record_trace`]: crate::Server::record_trace
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # use tower_lsp::jsonrpc::Result;
+//! # use tower_lsp::lsp_types::*;
+//! # use tower_lsp::{Client, LanguageServer, LspService};
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use tower_lsp::trace::Replay;
+//!
+//! # struct Backend {
+//! #     client: Client,
+//! # }
+//! #
+//! # #[tower_lsp::async_trait]
+//! # impl LanguageServer for Backend {
+//! #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//! #         Ok(InitializeResult::default())
+//! #     }
+//! #
+//! #     async fn shutdown(&self) -> Result<()> {
+//! #         Ok(())
+//! #     }
+//! # }
+//! #
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let trace = BufReader::new(File::open("session.jsonl").unwrap());
+//! let replay = Replay::from_reader(trace).unwrap();
+//!
+//! let (service, socket) = LspService::new(|client| Backend { client });
+//! for divergence in replay.run(service, socket).await {
+//!     eprintln!("{}", divergence);
+//! }
+//! # }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io::{self, BufRead, Write};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower::Service;
+use tracing::{error, warn};
+
+use crate::connection::Connection;
+use crate::jsonrpc::{self, Error, Id, Message, Request, Response};
+use crate::ClientSocket;
+
+/// Direction of a recorded message.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Message sent by the client to the server.
+    Incoming,
+    /// Message sent by the server to the client.
+    Outgoing,
+}
+
+/// A single message recorded in a trace.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TraceEntry {
+    /// Time at which the message was received or sent, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Whether the message was received or sent by the server.
+    pub direction: Direction,
+    /// The recorded message.
+    pub message: Message,
+}
+
+/// Writer of trace entries, shared by both halves of a [`Server`](crate::Server).
+pub(crate) struct Recorder(Mutex<Box<dyn Write + Send>>);
+
+impl Recorder {
+    pub(crate) fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        Recorder(Mutex::new(Box::new(writer)))
+    }
+
+    /// Appends the given message to the trace, logging any I/O error.
+    ///
+    /// The message may be any type serializing like a [`Message`], such as a [`Request`].
+    pub(crate) fn record<M: Serialize>(&self, direction: Direction, message: &M) {
+        #[derive(Serialize)]
+        struct Entry<'a, M> {
+            timestamp: u64,
+            direction: Direction,
+            message: &'a M,
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+
+        let entry = Entry {
+            timestamp,
+            direction,
+            message,
+        };
+
+        // Serialize outside of the lock, so that it is only held for writing the entry. Flushing
+        // keeps the trace complete even if the server hangs or is killed afterwards.
+        let result = serde_json::to_vec(&entry)
+            .map_err(io::Error::from)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                let mut writer = self.0.lock().unwrap();
+                writer.write_all(&line).and_then(|()| writer.flush())
+            });
+
+        if let Err(err) = result {
+            error!("failed to record message: {}", err);
+        }
+    }
+}
+
+impl Debug for Recorder {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Recorder").finish_non_exhaustive()
+    }
+}
+
+/// Response of the server which differs from the recorded one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// Method of the request, if it was sent by the client in the trace.
+    pub method: Option<String>,
+    /// Response recorded in the trace.
+    pub expected: Response,
+    /// Response sent by the server during the replay, or `None` if it stopped before responding.
+    pub actual: Option<Response>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let id = self.expected.id();
+        match &self.method {
+            Some(method) => write!(f, "response to `{}` request {} diverged", method, id)?,
+            None => write!(f, "response to request {} diverged", id)?,
+        }
+
+        let expected = serde_json::to_string(&self.expected).unwrap();
+        let actual = match &self.actual {
+            Some(actual) => serde_json::to_string(actual).unwrap(),
+            None => "none".to_owned(),
+        };
+
+        write!(f, ": expected {}, got {}", expected, actual)
+    }
+}
+
+/// Driver feeding the client side of a recorded session back into a service.
+///
+/// The messages received from the client are sent to the service in recorded order. Whenever the
+/// trace contains a response of the server, the replay waits for the service to send its own
+/// response to the same request and compares both. Requests sent by the service to the client are
+/// answered with the recorded responses to requests of the same method, in order, or else with a
+/// "Method not found" error. Timestamps are ignored.
+///
+/// Responses with a `null` ID, which answer malformed messages that are not recorded, are never
+/// compared.
+///
+/// See the [module-level documentation](self) for an example.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    entries: Vec<TraceEntry>,
+}
+
+impl Replay {
+    /// Creates a new `Replay` of the given trace entries.
+    pub fn new(entries: Vec<TraceEntry>) -> Self {
+        Replay { entries }
+    }
+
+    /// Reads a trace in JSON lines format, as written by
+    /// [`Server::record_trace`](crate::Server::record_trace).
+    ///
+    /// Empty lines are skipped.
+    pub fn from_reader<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str(&line)?);
+            }
+        }
+
+        Ok(Replay::new(entries))
+    }
+
+    /// Replays the trace against the given service and client socket.
+    ///
+    /// Returns every response of the service which differs from the recorded one, in recorded
+    /// order.
+    pub async fn run<T>(self, service: T, socket: ClientSocket) -> Vec<Divergence>
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        let mut session = Session::new(&self.entries, service, socket);
+        let mut methods = HashMap::new();
+        let mut divergences = Vec::new();
+
+        for entry in self.entries {
+            match entry.direction {
+                Direction::Incoming => {
+                    let msg = match entry.message {
+                        Message::Request(req) => Message::Request(req),
+                        Message::Response(_) => continue,
+                        Message::Batch(batch) => {
+                            let batch: Vec<_> = batch
+                                .into_iter()
+                                .filter(|msg| !matches!(msg, Message::Response(_)))
+                                .collect();
+
+                            if batch.is_empty() {
+                                continue;
+                            }
+
+                            Message::Batch(batch)
+                        }
+                    };
+
+                    for req in requests(&msg) {
+                        if let Some(id) = req.id() {
+                            methods.insert(id.clone(), req.method().to_owned());
+                        }
+                    }
+
+                    session.connection.send(msg);
+                }
+                Direction::Outgoing => {
+                    for expected in responses(entry.message) {
+                        let id = expected.id().clone();
+                        if id == Id::Null {
+                            continue;
+                        }
+
+                        let actual = session.response(&id).await;
+                        if actual.as_ref() != Some(&expected) {
+                            divergences.push(Divergence {
+                                method: methods.get(&id).cloned(),
+                                expected,
+                                actual,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        session.finish().await;
+        divergences
+    }
+}
+
+/// Connection between the replayed client and the service.
+struct Session {
+    connection: Connection,
+    answers: HashMap<String, VecDeque<jsonrpc::Result<Value>>>,
+    responses: HashMap<Id, Response>,
+}
+
+impl Session {
+    fn new<T>(entries: &[TraceEntry], service: T, socket: ClientSocket) -> Self
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        // Pair the recorded server-to-client requests with the responses of the client.
+        let mut pending = HashMap::new();
+        let mut answers: HashMap<_, VecDeque<_>> = HashMap::new();
+        for entry in entries {
+            match entry.direction {
+                Direction::Outgoing => {
+                    for req in requests(&entry.message) {
+                        if let Some(id) = req.id() {
+                            pending.insert(id.clone(), req.method().to_owned());
+                        }
+                    }
+                }
+                Direction::Incoming => {
+                    for res in responses(entry.message.clone()) {
+                        let (id, result) = res.into_parts();
+                        if let Some(method) = pending.remove(&id) {
+                            answers.entry(method).or_default().push_back(result);
+                        }
+                    }
+                }
+            }
+        }
+
+        Session {
+            connection: Connection::new(service, socket),
+            answers,
+            responses: HashMap::new(),
+        }
+    }
+
+    /// Waits for the response of the server to the given request.
+    async fn response(&mut self, id: &Id) -> Option<Response> {
+        loop {
+            if let Some(res) = self.responses.remove(id) {
+                return Some(res);
+            }
+
+            let msg = self.connection.recv().await?;
+            self.handle(msg);
+        }
+    }
+
+    /// Closes the input of the server and waits for it to stop.
+    async fn finish(mut self) {
+        self.connection.close();
+        while let Some(msg) = self.connection.recv().await {
+            self.handle(msg);
+        }
+    }
+
+    fn handle(&mut self, msg: Message) {
+        match msg {
+            Message::Request(req) => {
+                if let Some(id) = req.id().cloned() {
+                    let answer = self.answers.get_mut(req.method());
+                    let result = answer.and_then(VecDeque::pop_front).unwrap_or_else(|| {
+                        warn!("no recorded response to `{}` request", req.method());
+                        Err(Error::method_not_found())
+                    });
+
+                    let res = Response::from_parts(id, result);
+                    self.connection.send(Message::Response(res));
+                }
+            }
+            Message::Response(res) => {
+                self.responses.insert(res.id().clone(), res);
+            }
+            Message::Batch(batch) => batch.into_iter().for_each(|msg| self.handle(msg)),
+        }
+    }
+}
+
+/// Returns the requests and notifications contained in the given message.
+fn requests(msg: &Message) -> Vec<&Request> {
+    match msg {
+        Message::Request(req) => vec![req],
+        Message::Response(_) => Vec::new(),
+        Message::Batch(batch) => batch.iter().flat_map(requests).collect(),
+    }
+}
+
+/// Returns the responses contained in the given message.
+fn responses(msg: Message) -> Vec<Response> {
+    match msg {
+        Message::Request(_) => Vec::new(),
+        Message::Response(res) => vec![res],
+        Message::Batch(batch) => batch.into_iter().flat_map(responses).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::*;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{Client, LanguageServer, LspService};
+
+    #[derive(Debug)]
+    struct Mock {
+        client: Client,
+    }
+
+    #[async_trait::async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: InitializeParams) -> jsonrpc::Result<InitializeResult> {
+            Ok(InitializeResult::default())
+        }
+
+        async fn shutdown(&self) -> jsonrpc::Result<()> {
+            Ok(())
+        }
+
+        async fn hover(&self, _: HoverParams) -> jsonrpc::Result<Option<Hover>> {
+            let item = ConfigurationItem {
+                scope_uri: None,
+                section: Some("mock".into()),
+            };
+
+            let values = self.client.configuration(vec![item]).await?;
+            Ok(Some(Hover {
+                contents: HoverContents::Scalar(MarkedString::String(values[0].to_string())),
+                range: None,
+            }))
+        }
+    }
+
+    fn entry(direction: &str, message: Value) -> String {
+        let entry = json!({ "timestamp": 0, "direction": direction, "message": message });
+        entry.to_string()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn replays_session() {
+        let capabilities = json!({ "workspace": { "configuration": true } });
+        let position = json!({
+            "textDocument": { "uri": "file:///foo.rs" },
+            "position": { "line": 0, "character": 0 },
+        });
+
+        let trace = [
+            entry(
+                "incoming",
+                json!({"jsonrpc":"2.0","method":"initialize","params":{"capabilities":capabilities},"id":0}),
+            ),
+            entry(
+                "outgoing",
+                json!({"jsonrpc":"2.0","result":{"capabilities":{"hoverProvider":true}},"id":0}),
+            ),
+            entry(
+                "incoming",
+                json!({"jsonrpc":"2.0","method":"initialized","params":{}}),
+            ),
+            String::new(),
+            entry(
+                "incoming",
+                json!({"jsonrpc":"2.0","method":"textDocument/hover","params":position,"id":1}),
+            ),
+            entry(
+                "outgoing",
+                json!({"jsonrpc":"2.0","method":"workspace/configuration","params":{"items":[{"section":"mock"}]},"id":0}),
+            ),
+            entry(
+                "incoming",
+                json!({"jsonrpc":"2.0","result":["recorded"],"id":0}),
+            ),
+            entry(
+                "outgoing",
+                json!({"jsonrpc":"2.0","result":{"contents":"\"recorded\""},"id":1}),
+            ),
+            entry(
+                "incoming",
+                json!({"jsonrpc":"2.0","method":"shutdown","id":2}),
+            ),
+            entry("outgoing", json!({"jsonrpc":"2.0","result":null,"id":2})),
+            entry("incoming", json!({"jsonrpc":"2.0","method":"exit"})),
+        ];
+
+        let replay = Replay::from_reader(trace.join("\n").as_bytes()).unwrap();
+        let (service, socket) = LspService::new(|client| Mock { client });
+        let divergences = replay.run(service, socket).await;
+
+        let expected =
+            json!({"jsonrpc":"2.0","result":{"capabilities":{"hoverProvider":true}},"id":0});
+        let actual = json!({"jsonrpc":"2.0","result":{"capabilities":{}},"id":0});
+        assert_eq!(
+            divergences,
+            [Divergence {
+                method: Some("initialize".into()),
+                expected: serde_json::from_value(expected).unwrap(),
+                actual: Some(serde_json::from_value(actual).unwrap()),
+            }]
+        );
+    }
+}
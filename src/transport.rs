@@ -10,16 +10,25 @@ use tokio::io::{AsyncRead, AsyncWrite};
 #[cfg(feature = "runtime-tokio")]
 use tokio_util::codec::{FramedRead, FramedWrite};
 
-use futures::channel::mpsc;
-use futures::{future, join, stream, FutureExt, Sink, SinkExt, Stream, StreamExt, TryFutureExt};
+use std::fmt::Display;
+use std::future::Future;
+use std::io::Write;
+use std::task::Poll;
+use std::time::Duration;
+
+use futures::channel::{mpsc, oneshot};
+use futures::future::{self, BoxFuture, Either};
+use futures::{join, stream, FutureExt, Sink, SinkExt, Stream, StreamExt, TryFutureExt};
+use serde_json::Value;
 use tower::Service;
-use tracing::error;
+use tracing::{error, info, warn};
 
 use crate::codec::{LanguageServerCodec, ParseError};
-use crate::jsonrpc::{Error, Id, Message, Request, Response};
+use crate::jsonrpc::{Error, Id, Incoming, Message, Request, Response};
 use crate::service::{ClientSocket, RequestStream, ResponseSink};
+use crate::trace::{Direction, Recorder};
 
-const DEFAULT_MAX_CONCURRENCY: usize = 4;
+pub(crate) const DEFAULT_MAX_CONCURRENCY: usize = 4;
 const MESSAGE_QUEUE_SIZE: usize = 100;
 
 /// Trait implemented by client loopback sockets.
@@ -47,19 +56,25 @@ impl Loopback for ClientSocket {
     }
 }
 
-/// Server for processing requests and responses on standard I/O or TCP.
+/// Server for processing requests and responses on standard I/O, TCP or message channels.
 #[derive(Debug)]
 pub struct Server<I, O, L = ClientSocket> {
     stdin: I,
     stdout: O,
     loopback: L,
     max_concurrency: usize,
+    process_watch: Option<Duration>,
+    recorder: Option<Recorder>,
 }
 
+/// Input or output of a [`Server`] carrying whole JSON-RPC messages.
+///
+/// See [`Server::from_messages`] for details.
+#[derive(Debug)]
+pub struct Messages<T>(T);
+
 impl<I, O, L> Server<I, O, L>
 where
-    I: AsyncRead + Unpin,
-    O: AsyncWrite,
     L: Loopback,
     <L::ResponseSink as Sink<Response>>::Error: std::error::Error,
 {
@@ -70,6 +85,8 @@ where
             stdout,
             loopback: socket,
             max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            process_watch: None,
+            recorder: None,
         }
     }
 
@@ -83,6 +100,17 @@ where
     ///
     /// If not explicitly specified, `max` defaults to 4.
     ///
+    /// A [JSON-RPC batch] counts as a single request towards this limit, regardless of how many
+    /// requests it contains, since all of them are processed concurrently.
+    ///
+    /// [JSON-RPC batch]: https://www.jsonrpc.org/specification#batch
+    ///
+    /// Note that [`LspService`] processes notifications sequentially by default, regardless of
+    /// this setting. See [`LspServiceBuilder::ordered_notifications`] for details.
+    ///
+    /// [`LspService`]: crate::LspService
+    /// [`LspServiceBuilder::ordered_notifications`]: crate::LspServiceBuilder::ordered_notifications
+    ///
     /// # Preference over standard `tower` middleware
     ///
     /// The [`ConcurrencyLimit`] and [`Buffer`] middlewares provided by `tower` rely on
@@ -98,37 +126,148 @@ where
         self
     }
 
-    /// Spawns the service with messages read through `stdin` and responses written to `stdout`.
-    pub async fn serve<T>(self, mut service: T)
+    /// Stops the server once the client process exits, checking for it every `interval`.
+    ///
+    /// The process is identified by the `processId` sent by the client in the [`initialize`]
+    /// request, which is meant to let servers shut down when the editor dies without closing the
+    /// connection. Once the process is gone, the service is sent an `exit` notification, which
+    /// cancels any pending requests, and `serve` returns without waiting for the input to end.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    ///
+    /// If not explicitly specified, the client process is not watched.
+    ///
+    /// # Compatibility
+    ///
+    /// This is only supported on Linux, and does nothing on other platforms.
+    pub fn watch_client_process(mut self, interval: Duration) -> Self {
+        self.process_watch = Some(interval);
+        self
+    }
+
+    /// Records every message exchanged with the client into `trace`.
+    ///
+    /// Each message is written as a JSON line holding its timestamp, direction and contents, after
+    /// being decoded from the input or before being encoded to the output. Messages which fail to
+    /// be decoded are not recorded. See the [`trace`](crate::trace) module for replaying the
+    /// recorded session.
+    ///
+    /// Entries are written synchronously by the task serving the connection, and `trace` is
+    /// flushed after each of them, so the trace is complete up to the last message even if the
+    /// server hangs or is killed.
+    ///
+    /// If not explicitly specified, no messages are recorded.
+    pub fn record_trace<W>(mut self, trace: W) -> Self
     where
+        W: Write + Send + 'static,
+    {
+        self.recorder = Some(Recorder::new(trace));
+        self
+    }
+
+    /// Serves the service with messages read from and written to the input and output created
+    /// from `stdin` and `stdout` by `transport`.
+    ///
+    /// Messages which fail to be decoded are reported by `input` as errors, which are returned to
+    /// the client as error responses.
+    pub(crate) async fn serve_transport<In, Out, T, F>(
+        self,
+        transport: impl FnOnce(I, O) -> (In, Out),
+        mut service: T,
+        signal: F,
+    ) where
+        In: Stream<Item = Result<Incoming, Error>>,
+        Out: Sink<Message>,
+        Out::Error: Display,
         T: Service<Request, Response = Option<Response>> + Send + 'static,
         T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
         T::Future: Send,
+        F: Future<Output = ()>,
     {
         let (client_requests, mut client_responses) = self.loopback.split();
-        let (client_requests, client_abort) = stream::abortable(client_requests);
+        let (stop_client_requests, stopped) = oneshot::channel();
+        let client_requests = drain_until(client_requests, stopped);
         let (mut responses_tx, responses_rx) = mpsc::channel(0);
         let (mut server_tasks_tx, server_tasks_rx) = mpsc::channel(MESSAGE_QUEUE_SIZE);
 
-        let mut framed_stdin = FramedRead::new(self.stdin, LanguageServerCodec::default());
-        let framed_stdout = FramedWrite::new(self.stdout, LanguageServerCodec::default());
+        let (input, output) = transport(self.stdin, self.stdout);
+        futures::pin_mut!(input);
 
         let process_server_tasks = server_tasks_rx
             .buffer_unordered(self.max_concurrency)
             .filter_map(future::ready)
-            .map(|res| Ok(Message::Response(res)))
+            .map(Ok)
             .forward(responses_tx.clone().sink_map_err(|_| unreachable!()))
             .map(|_| ());
 
+        let recorder = self.recorder.as_ref();
         let print_output = stream::select(responses_rx, client_requests.map(Message::Request))
+            .inspect(|msg| {
+                if let Some(recorder) = recorder {
+                    recorder.record(Direction::Outgoing, msg);
+                }
+            })
             .map(Ok)
-            .forward(framed_stdout.sink_map_err(|e| error!("failed to encode message: {}", e)))
+            .forward(output.sink_map_err(|e| error!("failed to encode message: {}", e)))
             .map(|_| ());
 
+        let process_watch = self.process_watch;
         let read_input = async {
-            while let Some(msg) = framed_stdin.next().await {
+            let mut client_exited: BoxFuture<'static, ()> = future::pending().boxed();
+            futures::pin_mut!(signal);
+
+            loop {
+                let stop = future::select(&mut client_exited, signal.as_mut());
+                let msg = match future::select(input.next(), stop).await {
+                    Either::Left((Some(msg), _)) => msg,
+                    Either::Left((None, _)) => break,
+                    Either::Right((stop, _)) => {
+                        match stop {
+                            Either::Left(_) => info!("client process exited, stopping"),
+                            Either::Right(_) => info!("shutdown signal received, stopping"),
+                        }
+
+                        if future::poll_fn(|cx| service.poll_ready(cx)).await.is_ok() {
+                            let _ = service.call(Request::build("exit").finish()).await;
+                        }
+                        break;
+                    }
+                };
+
+                match (recorder, &msg) {
+                    (Some(recorder), Ok(Incoming::Request(req))) => {
+                        recorder.record(Direction::Incoming, req);
+                    }
+                    (Some(recorder), Ok(Incoming::Response(res))) => {
+                        recorder.record(Direction::Incoming, res);
+                    }
+                    (Some(recorder), Ok(Incoming::Batch(batch))) => {
+                        // Elements which failed to decode are not recorded.
+                        let batch: Vec<_> =
+                            batch.iter().filter_map(|msg| msg.as_ref().ok()).collect();
+                        recorder.record(Direction::Incoming, &batch);
+                    }
+                    _ => {}
+                }
+
+                let initialize = match &msg {
+                    Ok(Incoming::Request(req)) => Some(req),
+                    _ => None,
+                };
+
+                if let (Some(interval), Some(req)) = (process_watch, initialize) {
+                    let pid = req.params().and_then(|p| p.get("processId"));
+                    match pid.and_then(Value::as_u64) {
+                        Some(pid) if req.method() == "initialize" => match u32::try_from(pid) {
+                            Ok(pid) => client_exited = process_exited(pid, interval).boxed(),
+                            Err(_) => warn!("invalid client process ID {}, not watching it", pid),
+                        },
+                        _ => {}
+                    }
+                }
+
                 match msg {
-                    Ok(Message::Request(req)) => {
+                    Ok(Incoming::Request(req)) => {
                         if let Err(err) = future::poll_fn(|cx| service.poll_ready(cx)).await {
                             error!("{}", display_sources(err.into().as_ref()));
                             return;
@@ -139,17 +278,72 @@ where
                             None
                         });
 
-                        server_tasks_tx.send(fut).await.unwrap();
+                        let task = fut.map(|res| res.map(Message::Response));
+                        server_tasks_tx.send(Either::Left(task)).await.unwrap();
                     }
-                    Ok(Message::Response(res)) => {
+                    Ok(Incoming::Response(res)) => {
                         if let Err(err) = client_responses.send(res).await {
                             error!("{}", display_sources(&err));
                             return;
                         }
                     }
+                    Ok(Incoming::Batch(batch)) if batch.is_empty() => {
+                        let res = Response::from_error(Id::Null, Error::invalid_request());
+                        responses_tx.send(Message::Response(res)).await.unwrap();
+                    }
+                    Ok(Incoming::Batch(batch)) => {
+                        let mut calls = Vec::with_capacity(batch.len());
+                        let mut invalid = Vec::new();
+
+                        for msg in batch {
+                            match msg {
+                                Ok(Message::Request(req)) => {
+                                    let ready = future::poll_fn(|cx| service.poll_ready(cx)).await;
+                                    if let Err(err) = ready {
+                                        error!("{}", display_sources(err.into().as_ref()));
+                                        return;
+                                    }
+
+                                    calls.push(service.call(req).unwrap_or_else(|err| {
+                                        error!("{}", display_sources(err.into().as_ref()));
+                                        None
+                                    }));
+                                }
+                                Ok(Message::Response(res)) => {
+                                    if let Err(err) = client_responses.send(res).await {
+                                        error!("{}", display_sources(&err));
+                                        return;
+                                    }
+                                }
+                                Ok(Message::Batch(_)) => {
+                                    error!("received nested batch message, ignoring");
+                                    let err = Error::invalid_request();
+                                    invalid.push(Response::from_error(Id::Null, err));
+                                }
+                                Err(err) => {
+                                    error!("received invalid batch element, ignoring");
+                                    invalid.push(Response::from_error(Id::Null, err));
+                                }
+                            }
+                        }
+
+                        // Notifications produce no responses, so only the responses to requests
+                        // (if any) are written back to the client as a single batch.
+                        let task = future::join_all(calls).map(move |responses| {
+                            let batch: Vec<_> = responses
+                                .into_iter()
+                                .flatten()
+                                .chain(invalid)
+                                .map(Message::Response)
+                                .collect();
+
+                            (!batch.is_empty()).then_some(Message::Batch(batch))
+                        });
+
+                        server_tasks_tx.send(Either::Right(task)).await.unwrap();
+                    }
                     Err(err) => {
-                        error!("failed to decode message: {}", err);
-                        let res = Response::from_error(Id::Null, to_jsonrpc_error(err));
+                        let res = Response::from_error(Id::Null, err);
                         responses_tx.send(Message::Response(res)).await.unwrap();
                     }
                 }
@@ -157,13 +351,207 @@ where
 
             server_tasks_tx.disconnect();
             responses_tx.disconnect();
-            client_abort.abort();
+            let _ = stop_client_requests.send(());
         };
 
         join!(print_output, read_input, process_server_tasks);
     }
 }
 
+impl<I, O, L> Server<I, O, L>
+where
+    I: AsyncRead + Unpin,
+    O: AsyncWrite,
+    L: Loopback,
+    <L::ResponseSink as Sink<Response>>::Error: std::error::Error,
+{
+    /// Spawns the service with messages read through `stdin` and responses written to `stdout`.
+    ///
+    /// Incoming [JSON-RPC batches] are supported. All requests and notifications contained in a
+    /// batch are sent through the service concurrently, and their responses are written back to
+    /// `stdout` as a single batch once every one of them has completed. Elements of a batch which
+    /// are not valid messages are answered with "Invalid request" errors in the same batch.
+    ///
+    /// [JSON-RPC batches]: https://www.jsonrpc.org/specification#batch
+    ///
+    /// This returns once `stdin` reaches EOF. See [`Server::serve_with_shutdown`] for stopping
+    /// the server from the outside.
+    pub async fn serve<T>(self, service: T)
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        self.serve_with_shutdown(service, future::pending()).await
+    }
+
+    /// Spawns the service like [`Server::serve`], stopping it gracefully once `signal` resolves.
+    ///
+    /// When the signal is triggered, the server stops reading from `stdin` and sends an `exit`
+    /// notification to the service, which cancels any pending requests. It then returns once all
+    /// in-flight responses and queued server-to-client requests have been written to `stdout`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use tower_lsp::jsonrpc::Result;
+    /// # use tower_lsp::lsp_types::*;
+    /// # use tower_lsp::{LanguageServer, LspService, Server};
+    /// #
+    /// # struct Mock;
+    /// #
+    /// # #[tower_lsp::async_trait]
+    /// # impl LanguageServer for Mock {
+    /// #     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    /// #         Ok(InitializeResult::default())
+    /// #     }
+    /// #
+    /// #     async fn shutdown(&self) -> Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// # let (stdin, stdout) = (tokio::io::empty(), tokio::io::sink());
+    /// # #[cfg(feature = "runtime-agnostic")]
+    /// # use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+    /// # #[cfg(feature = "runtime-agnostic")]
+    /// # let (stdin, stdout) = (stdin.compat(), stdout.compat_write());
+    /// use futures::channel::oneshot;
+    ///
+    /// let (service, socket) = LspService::new(|_| Mock);
+    /// let (stop, signal) = oneshot::channel::<()>();
+    ///
+    /// let server = Server::new(stdin, stdout, socket);
+    /// let serve = server.serve_with_shutdown(service, async move {
+    ///     signal.await.ok();
+    /// });
+    ///
+    /// // Dropping or sending on `stop` makes the server return.
+    /// drop(stop);
+    /// serve.await;
+    /// # }
+    /// ```
+    pub async fn serve_with_shutdown<T, F>(self, service: T, signal: F)
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+        F: Future<Output = ()>,
+    {
+        let transport = |stdin, stdout| {
+            let input = FramedRead::new(stdin, LanguageServerCodec::default()).map(|msg| {
+                msg.map_err(|err| {
+                    error!("failed to decode message: {}", err);
+                    to_jsonrpc_error(err)
+                })
+            });
+
+            (
+                input,
+                FramedWrite::new(stdout, LanguageServerCodec::default()),
+            )
+        };
+
+        self.serve_transport(transport, service, signal).await
+    }
+}
+
+impl<S, K, L> Server<Messages<S>, Messages<K>, L>
+where
+    S: Stream<Item = Message>,
+    K: Sink<Message>,
+    K::Error: Display,
+    L: Loopback,
+    <L::ResponseSink as Sink<Response>>::Error: std::error::Error,
+{
+    /// Creates a new `Server` exchanging whole JSON-RPC messages with the client through `input`
+    /// and `output`.
+    ///
+    /// Unlike [`Server::new`], no `Content-Length` framing is involved, which suits transports
+    /// that already carry whole messages, such as in-process channels, web workers or IPC queues.
+    pub fn from_messages(input: S, output: K, socket: L) -> Self {
+        Server::new(Messages(input), Messages(output), socket)
+    }
+
+    /// Spawns the service with messages received from `input` and sent to `output`.
+    ///
+    /// This behaves like the byte stream variant of [`Server::serve`], and returns once `input`
+    /// ends.
+    pub async fn serve<T>(self, service: T)
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        self.serve_with_shutdown(service, future::pending()).await
+    }
+
+    /// Spawns the service like [`Server::serve`], stopping it gracefully once `signal` resolves.
+    pub async fn serve_with_shutdown<T, F>(self, service: T, signal: F)
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+        F: Future<Output = ()>,
+    {
+        let transport = |input: Messages<S>, output: Messages<K>| {
+            (input.0.map(|msg| Ok(Incoming::from(msg))), output.0)
+        };
+        self.serve_transport(transport, service, signal).await
+    }
+}
+
+/// Yields the items of `stream` until `stopped` resolves, and then only the items which are
+/// immediately available.
+fn drain_until<S>(stream: S, stopped: oneshot::Receiver<()>) -> impl Stream<Item = S::Item>
+where
+    S: Stream,
+{
+    let mut stream = Box::pin(stream);
+    let mut stopped = Some(stopped);
+
+    stream::poll_fn(move |cx| {
+        match stopped.as_mut().map(|rx| rx.poll_unpin(cx)) {
+            Some(Poll::Pending) => return stream.as_mut().poll_next(cx),
+            Some(Poll::Ready(_)) => stopped = None,
+            None => {}
+        }
+
+        match stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(item)) => Poll::Ready(Some(item)),
+            Poll::Ready(None) | Poll::Pending => Poll::Ready(None),
+        }
+    })
+}
+
+/// Resolves once the process with the given ID has exited.
+#[cfg(target_os = "linux")]
+async fn process_exited(pid: u32, interval: Duration) {
+    use futures_timer::Delay;
+
+    // Exited processes remain listed as zombies until they are reaped by their parent.
+    let is_alive = || match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => match stat.rsplit_once(')') {
+            Some((_, fields)) => !fields.trim_start().starts_with('Z'),
+            None => true,
+        },
+        Err(_) => false,
+    };
+
+    while is_alive() {
+        Delay::new(interval).await;
+    }
+}
+
+/// Resolves once the process with the given ID has exited.
+#[cfg(not(target_os = "linux"))]
+async fn process_exited(pid: u32, _: Duration) {
+    info!("cannot watch client process {} on this platform", pid);
+    future::pending().await
+}
+
 fn display_sources(error: &dyn std::error::Error) -> String {
     if let Some(source) = error.source() {
         format!("{}: {}", error, display_sources(source))
@@ -201,6 +589,7 @@ mod tests {
     use futures::{future, sink, stream};
 
     use super::*;
+    use crate::trace::TraceEntry;
 
     const REQUEST: &str = r#"{"jsonrpc":"2.0","method":"initialize","params":{},"id":1}"#;
     const RESPONSE: &str = r#"{"jsonrpc":"2.0","result":{"capabilities":{}},"id":1}"#;
@@ -217,9 +606,9 @@ mod tests {
             Poll::Ready(Ok(()))
         }
 
-        fn call(&mut self, _: Request) -> Self::Future {
+        fn call(&mut self, req: Request) -> Self::Future {
             let response = serde_json::from_str(RESPONSE).unwrap();
-            future::ok(Some(response))
+            future::ok(req.id().map(|_| response))
         }
     }
 
@@ -286,4 +675,208 @@ mod tests {
         let output = format!("Content-Length: {}\r\n\r\n{}", err.len(), err).into_bytes();
         assert_eq!(stdout, output);
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn handles_batch_messages() {
+        let notification = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#;
+        let batch = format!("[{REQUEST},{notification},{REQUEST}]");
+        let message = format!("Content-Length: {}\r\n\r\n{}", batch.len(), batch).into_bytes();
+        let (mut stdin, mut stdout) = (Cursor::new(message), Vec::new());
+
+        Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
+            .serve(MockService)
+            .await;
+
+        let responses = format!("[{RESPONSE},{RESPONSE}]");
+        let output = format!("Content-Length: {}\r\n\r\n{}", responses.len(), responses);
+        assert_eq!(String::from_utf8(stdout).unwrap(), output);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn handles_invalid_batch_elements() {
+        let batch = format!("[1,{REQUEST}]");
+        let message = format!("Content-Length: {}\r\n\r\n{}", batch.len(), batch).into_bytes();
+        let (mut stdin, mut stdout) = (Cursor::new(message), Vec::new());
+
+        Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
+            .serve(MockService)
+            .await;
+
+        let err =
+            r#"{"jsonrpc":"2.0","error":{"code":-32600,"message":"Invalid request"},"id":null}"#;
+        let responses = format!("[{RESPONSE},{err}]");
+        let output = format!("Content-Length: {}\r\n\r\n{}", responses.len(), responses);
+        assert_eq!(String::from_utf8(stdout).unwrap(), output);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn handles_batch_of_notifications() {
+        let notification = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#;
+        let batch = format!("[{notification},{notification}]");
+        let message = format!("Content-Length: {}\r\n\r\n{}", batch.len(), batch).into_bytes();
+        let (mut stdin, mut stdout) = (Cursor::new(message), Vec::new());
+
+        Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
+            .serve(MockService)
+            .await;
+
+        assert!(stdout.is_empty());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn records_trace() {
+        /// Writer which only shares the written bytes once flushed.
+        #[derive(Default)]
+        struct SharedBuf {
+            buffered: Vec<u8>,
+            flushed: std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+        }
+
+        impl Write for SharedBuf {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.buffered.write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                let mut flushed = self.flushed.lock().unwrap();
+                flushed.append(&mut self.buffered);
+                Ok(())
+            }
+        }
+
+        let trace = SharedBuf::default();
+        let flushed = trace.flushed.clone();
+        let (mut stdin, mut stdout) = mock_stdio();
+        Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
+            .record_trace(trace)
+            .serve(MockService)
+            .await;
+
+        let trace = String::from_utf8(flushed.lock().unwrap().clone()).unwrap();
+        let entries: Vec<TraceEntry> = trace
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let messages: Vec<_> = entries.iter().map(|e| (e.direction, &e.message)).collect();
+        let request = serde_json::from_str(REQUEST).unwrap();
+        let response = serde_json::from_str(RESPONSE).unwrap();
+        assert_eq!(
+            messages,
+            [
+                (Direction::Incoming, &request),
+                (Direction::Outgoing, &response)
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn serves_on_message_channels() {
+        let request: Message = serde_json::from_str(REQUEST).unwrap();
+        let socket = MockLoopback(vec![serde_json::from_str(REQUEST).unwrap()]);
+
+        let input = stream::iter(vec![request.clone()]);
+        let (output, messages) = mpsc::unbounded();
+        Server::from_messages(input, output, socket)
+            .serve(MockService)
+            .await;
+
+        let response = serde_json::from_str(RESPONSE).unwrap();
+        let messages: Vec<_> = messages.collect().await;
+        assert_eq!(messages, [request, response]);
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn stops_on_shutdown_signal() {
+        use tokio::io::AsyncWriteExt;
+
+        // Keep the input open, so that the server only stops because of the signal.
+        let (mut input, stdin) = tokio::io::duplex(1024);
+        input.write_all(&mock_request()).await.unwrap();
+
+        let socket = MockLoopback(vec![serde_json::from_str(REQUEST).unwrap()]);
+        let mut stdout = Vec::new();
+        Server::new(stdin, &mut stdout, socket)
+            .serve_with_shutdown(MockService, future::ready(()))
+            .await;
+
+        let output: Vec<_> = mock_request().into_iter().chain(mock_response()).collect();
+        assert_eq!(stdout, output);
+    }
+
+    #[cfg(all(target_os = "linux", feature = "runtime-tokio"))]
+    #[tokio::test(flavor = "current_thread")]
+    async fn stops_when_client_process_exits() {
+        use tokio::io::AsyncWriteExt;
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","method":"initialize","params":{{"processId":{}}},"id":1}}"#,
+            child.id()
+        );
+        let message = format!("Content-Length: {}\r\n\r\n{}", request.len(), request);
+
+        // Keep the input open, so that the server only stops because of the exited process.
+        let (mut input, stdin) = tokio::io::duplex(1024);
+        input.write_all(message.as_bytes()).await.unwrap();
+
+        let mut stdout = Vec::new();
+        let serve = Server::new(stdin, &mut stdout, MockLoopback(vec![]))
+            .watch_client_process(Duration::from_millis(10))
+            .serve(MockService)
+            .boxed();
+        let timeout = futures_timer::Delay::new(Duration::from_secs(5));
+
+        let stopped = matches!(future::select(serve, timeout).await, Either::Left(_));
+        assert!(stopped, "server did not stop");
+        assert_eq!(stdout, mock_response());
+    }
+
+    #[cfg(all(target_os = "linux", feature = "runtime-tokio"))]
+    #[tokio::test(flavor = "current_thread")]
+    async fn ignores_out_of_range_client_process() {
+        use tokio::io::AsyncWriteExt;
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+
+        // Truncating this ID to `u32` would watch the exited process.
+        let pid = (1u64 << 32) + u64::from(child.id());
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","method":"initialize","params":{{"processId":{}}},"id":1}}"#,
+            pid
+        );
+        let message = format!("Content-Length: {}\r\n\r\n{}", request.len(), request);
+
+        let (mut input, stdin) = tokio::io::duplex(1024);
+        input.write_all(message.as_bytes()).await.unwrap();
+
+        let mut stdout = Vec::new();
+        let serve = Server::new(stdin, &mut stdout, MockLoopback(vec![]))
+            .watch_client_process(Duration::from_millis(10))
+            .serve(MockService)
+            .boxed();
+        let timeout = futures_timer::Delay::new(Duration::from_millis(200));
+
+        let stopped = matches!(future::select(serve, timeout).await, Either::Left(_));
+        assert!(!stopped, "server stopped");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn handles_empty_batch() {
+        let message = "Content-Length: 2\r\n\r\n[]".as_bytes().to_vec();
+        let (mut stdin, mut stdout) = (Cursor::new(message), Vec::new());
+
+        Server::new(&mut stdin, &mut stdout, MockLoopback(vec![]))
+            .serve(MockService)
+            .await;
+
+        let err =
+            r#"{"jsonrpc":"2.0","error":{"code":-32600,"message":"Invalid request"},"id":null}"#;
+        let output = format!("Content-Length: {}\r\n\r\n{}", err.len(), err).into_bytes();
+        assert_eq!(stdout, output);
+    }
 }
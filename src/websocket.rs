@@ -0,0 +1,234 @@
+//! WebSocket transport for [`Server`].
+//!
+//! Unlike standard I/O and TCP, where each JSON-RPC message is preceded by a `Content-Length`
+//! header, this transport maps every message to a single WebSocket text frame, as expected by
+//! browser-based clients such as the Monaco editor. Incoming binary frames are accepted as well.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # use tower_lsp::jsonrpc::Result;
+//! # use tower_lsp::lsp_types::*;
+//! # use tower_lsp::{Client, LanguageServer, LspService, Server};
+//! use async_tungstenite::tokio::accept_async;
+//! use tokio::net::TcpListener;
+//! use tower_lsp::websocket;
+//!
+//! struct Backend {
+//!     client: Client,
+//! }
+//!
+//! #[tower_lsp::async_trait]
+//! impl LanguageServer for Backend {
+//!     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//!         Ok(InitializeResult::default())
+//!     }
+//!
+//!     async fn shutdown(&self) -> Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let listener = TcpListener::bind("127.0.0.1:9257").await.unwrap();
+//!     let (stream, _) = listener.accept().await.unwrap();
+//!     let (read, write) = websocket::split(accept_async(stream).await.unwrap());
+//!
+//!     let (service, socket) = LspService::new(|client| Backend { client });
+//!     Server::new(read, write, socket).serve(service).await;
+//! }
+//! ```
+
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+
+use async_tungstenite::tungstenite::Message as WsMessage;
+use async_tungstenite::WebSocketStream;
+use futures::future;
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::stream::{SplitSink, SplitStream};
+use futures::{Sink, SinkExt, StreamExt};
+use tower::Service;
+use tracing::error;
+
+use crate::jsonrpc::{Error, Incoming, Request, Response};
+use crate::transport::{Loopback, Server};
+
+/// Receiving half of a WebSocket connection, created by [`split`].
+pub struct WebSocketReader<S>(SplitStream<WebSocketStream<S>>);
+
+/// Sending half of a WebSocket connection, created by [`split`].
+pub struct WebSocketWriter<S>(SplitSink<WebSocketStream<S>, WsMessage>);
+
+/// Splits the given WebSocket connection into halves which can be passed to [`Server::new`].
+pub fn split<S>(ws: WebSocketStream<S>) -> (WebSocketReader<S>, WebSocketWriter<S>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (sink, stream) = ws.split();
+    (WebSocketReader(stream), WebSocketWriter(sink))
+}
+
+impl<S> Debug for WebSocketReader<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("WebSocketReader").finish_non_exhaustive()
+    }
+}
+
+impl<S> Debug for WebSocketWriter<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("WebSocketWriter").finish_non_exhaustive()
+    }
+}
+
+impl<S, L> Server<WebSocketReader<S>, WebSocketWriter<S>, L>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    L: Loopback,
+    <L::ResponseSink as Sink<Response>>::Error: std::error::Error,
+{
+    /// Spawns the service with messages received from and sent to the WebSocket connection.
+    ///
+    /// This returns once the connection is closed. See [`Server::serve_with_shutdown`] for
+    /// stopping the server from the outside.
+    pub async fn serve<T>(self, service: T)
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+    {
+        self.serve_with_shutdown(service, future::pending()).await
+    }
+
+    /// Spawns the service like [`Server::serve`], stopping it gracefully once `signal` resolves.
+    ///
+    /// The WebSocket connection is closed before returning.
+    pub async fn serve_with_shutdown<T, F>(self, service: T, signal: F)
+    where
+        T: Service<Request, Response = Option<Response>> + Send + 'static,
+        T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T::Future: Send,
+        F: Future<Output = ()>,
+    {
+        let transport = |read: WebSocketReader<S>, write: WebSocketWriter<S>| {
+            let input = read
+                .0
+                .take_while(|msg| {
+                    future::ready(match msg {
+                        Ok(msg) => !msg.is_close(),
+                        Err(err) => {
+                            error!("failed to receive WebSocket message: {}", err);
+                            false
+                        }
+                    })
+                })
+                .filter_map(|msg| {
+                    future::ready(match msg {
+                        Ok(WsMessage::Text(text)) => Some(decode(text.as_bytes())),
+                        Ok(WsMessage::Binary(data)) => Some(decode(&data)),
+                        _ => None,
+                    })
+                });
+
+            let output = write.0.sink_map_err(|err| err.to_string()).with(|msg| {
+                let text = serde_json::to_string(&msg).map_err(|err| err.to_string());
+                future::ready(text.map(WsMessage::Text))
+            });
+
+            (input, output)
+        };
+
+        self.serve_transport(transport, service, signal).await
+    }
+}
+
+/// Decodes a single JSON-RPC message from the contents of a WebSocket frame.
+fn decode(data: &[u8]) -> Result<Incoming, Error> {
+    serde_json::from_slice(data).map_err(|err| {
+        error!("failed to decode message: {}", err);
+        if err.is_data() {
+            Error::invalid_request()
+        } else {
+            Error::parse_error()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use async_tungstenite::tokio::TokioAdapter;
+    use async_tungstenite::tungstenite::protocol::Role;
+    use futures::stream;
+
+    use super::*;
+
+    const REQUEST: &str = r#"{"jsonrpc":"2.0","method":"initialize","params":{},"id":1}"#;
+    const RESPONSE: &str = r#"{"jsonrpc":"2.0","result":{"capabilities":{}},"id":1}"#;
+
+    struct MockService;
+
+    impl Service<Request> for MockService {
+        type Response = Option<Response>;
+        type Error = String;
+        type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(
+            &mut self,
+            _: &mut std::task::Context,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: Request) -> Self::Future {
+            let response = serde_json::from_str(RESPONSE).unwrap();
+            future::ok(req.id().map(|_| response))
+        }
+    }
+
+    struct MockLoopback;
+
+    impl Loopback for MockLoopback {
+        type RequestStream = stream::Empty<Request>;
+        type ResponseSink = futures::sink::Drain<Response>;
+
+        fn split(self) -> (Self::RequestStream, Self::ResponseSink) {
+            (stream::empty(), futures::sink::drain())
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn maps_messages_to_frames() {
+        let (client, server) = tokio::io::duplex(1024);
+        let client =
+            WebSocketStream::from_raw_socket(TokioAdapter::new(client), Role::Client, None);
+        let server =
+            WebSocketStream::from_raw_socket(TokioAdapter::new(server), Role::Server, None);
+        let (mut client, server) = futures::join!(client, server);
+
+        let (read, write) = split(server);
+        let serve = Server::new(read, write, MockLoopback).serve(MockService);
+
+        let client = async move {
+            client.send(WsMessage::text(REQUEST)).await.unwrap();
+            client.send(WsMessage::text("{")).await.unwrap();
+            client.send(WsMessage::binary(REQUEST)).await.unwrap();
+
+            let mut frames = Vec::new();
+            for _ in 0..3 {
+                frames.push(client.next().await.unwrap().unwrap().into_text().unwrap());
+            }
+
+            client.close(None).await.unwrap();
+            frames
+        };
+
+        // Responses may be sent in any order.
+        let ((), mut frames) = futures::join!(serve, client);
+        frames.sort();
+
+        let parse_error =
+            r#"{"jsonrpc":"2.0","error":{"code":-32700,"message":"Parse error"},"id":null}"#;
+        assert_eq!(frames, [parse_error, RESPONSE, RESPONSE]);
+    }
+}
@@ -6,7 +6,7 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, FnArg, ItemTrait, LitStr, ReturnType, TraitItem};
+use syn::{parse_macro_input, FnArg, ImplItem, ItemImpl, ItemTrait, LitStr, ReturnType, TraitItem};
 
 /// Macro for generating LSP server implementation from [`lsp-types`](https://docs.rs/lsp-types).
 ///
@@ -32,6 +32,40 @@ pub fn rpc(attr: TokenStream, item: TokenStream) -> TokenStream {
     tokens.into()
 }
 
+/// Macro for recording which `tower_lsp::LanguageServer` methods a server implements.
+///
+/// This procedural macro annotates an `impl LanguageServer for ...` block and generates an
+/// implementation of `LanguageServer::implemented_methods()` listing the LSP method names of all
+/// methods defined in that block.
+#[proc_macro_attribute]
+pub fn implemented_methods(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        let attr = proc_macro2::TokenStream::from(attr);
+        return syn::Error::new_spanned(attr, "`#[implemented_methods]` takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let mut server_impl = parse_macro_input!(item as ItemImpl);
+
+    let handlers: Vec<_> = server_impl
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            ImplItem::Fn(m) if m.sig.ident != "implemented_methods" => Some(m.sig.ident.clone()),
+            _ => None,
+        })
+        .collect();
+
+    server_impl.items.push(syn::parse_quote! {
+        fn implemented_methods(&self) -> Option<&'static [&'static str]> {
+            Some(&[#(::tower_lsp::__private::methods::#handlers),*])
+        }
+    });
+
+    quote!(#server_impl).into()
+}
+
 struct MethodCall<'a> {
     rpc_name: String,
     handler_name: &'a syn::Ident,
@@ -48,11 +82,12 @@ fn parse_method_calls(lang_server_trait: &ItemTrait) -> Vec<MethodCall> {
             _ => continue,
         };
 
-        let attr = method
-            .attrs
-            .iter()
-            .find(|attr| attr.meta.path().is_ident("rpc"))
-            .expect("expected `#[rpc(name = \"foo\")]` attribute");
+        // Synchronous methods are helpers which are not exposed as RPC handlers.
+        let attr = match method.attrs.iter().find(|a| a.meta.path().is_ident("rpc")) {
+            Some(attr) => attr,
+            None if method.sig.asyncness.is_none() => continue,
+            None => panic!("expected `#[rpc(name = \"foo\")]` attribute"),
+        };
 
         let mut rpc_name = String::new();
         attr.parse_nested_meta(|meta| {
@@ -94,12 +129,33 @@ fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_ma
             let rpc_name = &method.rpc_name;
             let handler = &method.handler_name;
 
-            let layer = match &rpc_name[..] {
-                "initialize" => quote! { layers::Initialize::new(state.clone(), pending.clone()) },
+            let lifecycle = match &rpc_name[..] {
+                "initialize" => quote! {
+                    layers::Initialize::new(
+                        state.clone(),
+                        pending.clone(),
+                        client.clone(),
+                        implemented,
+                    )
+                },
                 "shutdown" => quote! { layers::Shutdown::new(state.clone(), pending.clone()) },
+                "initialized" | "workspace/didChangeConfiguration" => quote! {
+                    layers::Configuration::new(state.clone(), pending.clone(), client.clone())
+                },
+                "workspace/didChangeWorkspaceFolders" => quote! {
+                    layers::DidChangeWorkspaceFolders::new(
+                        state.clone(),
+                        pending.clone(),
+                        client.clone(),
+                    )
+                },
                 _ => quote! { layers::Normal::new(state.clone(), pending.clone()) },
             };
 
+            let layer = quote! {
+                tower::layer::util::Stack::new(middleware.for_method(#rpc_name), #lifecycle)
+            };
+
             // NOTE: In a perfect world, we could simply loop over each `MethodCall` and emit
             // `router.method(#rpc_name, S::#handler);` for each. While such an approach
             // works for inherent async functions and methods, it breaks with `async-trait` methods
@@ -140,6 +196,17 @@ fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_ma
         })
         .collect();
 
+    let rpc_names = methods.iter().map(|method| &method.rpc_name);
+    let method_names = methods.iter().map(|method| {
+        let (handler, rpc_name) = (method.handler_name, &method.rpc_name);
+        let doc = format!("Name of the method handled by `{}`.", handler);
+        quote! {
+            #[doc = #doc]
+            #[allow(non_upper_case_globals)]
+            pub const #handler: &str = #rpc_name;
+        }
+    });
+
     quote! {
         mod generated {
             use std::sync::Arc;
@@ -159,11 +226,31 @@ fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_ma
                 std::future::ready(())
             }
 
+            fn set_trace(params: SetTraceParams, c: &Client) -> Ready<()> {
+                c.set_trace(params.value);
+                std::future::ready(())
+            }
+
+            fn cancel_work_done_progress(params: WorkDoneProgressCancelParams, c: &Client) -> Ready<()> {
+                c.cancel_work_done_progress(&params.token);
+                std::future::ready(())
+            }
+
+            /// Names of all methods defined by the language server trait.
+            pub(crate) const LSP_METHODS: &[&str] = &[#(#rpc_names),*];
+
+            /// Names of the methods defined by the language server trait, keyed by handler.
+            pub mod methods {
+                #(#method_names)*
+            }
+
             pub(crate) fn register_lsp_methods<S>(
                 mut router: Router<S, ExitedError>,
                 state: Arc<ServerState>,
                 pending: Arc<Pending>,
                 client: Client,
+                middleware: &layers::Middleware,
+                implemented: Option<&'static [&'static str]>,
             ) -> Router<S, ExitedError>
             where
                 S: #trait_name,
@@ -176,6 +263,18 @@ fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_ma
                     move |_: &S, params| cancel_request(params, &p),
                     tower::layer::util::Identity::new(),
                 );
+                let c = client.clone();
+                router.method(
+                    "$/setTrace",
+                    move |_: &S, params| set_trace(params, &c),
+                    layers::Normal::new(state.clone(), pending.clone()),
+                );
+                let c = client.clone();
+                router.method(
+                    "window/workDoneProgress/cancel",
+                    move |_: &S, params| cancel_work_done_progress(params, &c),
+                    layers::Normal::new(state.clone(), pending.clone()),
+                );
                 router.method(
                     "exit",
                     |_: &S| std::future::ready(()),